@@ -0,0 +1,483 @@
+use std::fs::File;
+use std::i16;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::iter::Peekable;
+use std::str::Chars;
+use std::time::Duration;
+
+use clock;
+use computer_player;
+use computer_player::ComputerPlayer;
+use computer_player::Evaluator;
+use computer_player::PieceValues;
+use computer_player::Weights;
+use computer_player::DEFAULT_PIECE_VALUES;
+use computer_player::EVALUATOR_NAMES;
+use computer_player::SCORER_NAMES;
+use transposition_table;
+
+// Where the AIs are declared. Without it, the built in AIs are used.
+pub const AI_CONFIG_PATH: &'static str = "ais.toml";
+
+// A computer player, as declared in the config file. Each is a TOML table in an array named "ai":
+//
+//     [[ai]]
+//     name = "spaces_moves"
+//     scorers = { piece = 70, spaces = 7, moves = 1 }
+//     piece_values = [1, 3, 3, 5, 9]
+//     depth = 4
+//     movetime = 2.5
+//     quiescence = true
+//     hash_mb = 16
+//     randomness = true
+//
+// Only the name and scorers are required. Scorers that are left out get no weight, and the piece
+// values are for the pawn up to the queen. The depth and the time per move in seconds are used in
+// place of the game's, and the AI picks randomly between equally good moves if it has randomness.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AiDefinition {
+    pub name: String,
+    pub weights: Weights,
+    pub piece_values: PieceValues,
+    pub depth: Option<u8>,
+    pub movetime: Option<Duration>,
+    pub quiescence: bool,
+    pub hash_size_mb: usize,
+    pub randomness: bool,
+}
+
+impl AiDefinition {
+    pub fn new(name: &str, weights: Weights, piece_values: PieceValues) -> AiDefinition {
+        AiDefinition {
+            name: name.to_owned(),
+            weights: weights,
+            piece_values: piece_values,
+            depth: None,
+            movetime: None,
+            quiescence: true,
+            hash_size_mb: transposition_table::DEFAULT_SIZE_MB,
+            randomness: true,
+        }
+    }
+
+    pub fn evaluator(&self) -> Evaluator {
+        let mut evaluator = computer_player::weighted_evaluator(self.weights, self.piece_values);
+        evaluator.set_quiescence(self.quiescence);
+        evaluator
+    }
+
+    pub fn create_player(&self) -> ComputerPlayer {
+        let mut player = ComputerPlayer::new(&self.name, self.evaluator());
+        player.set_limits(self.depth, self.movetime);
+        player.set_randomness(self.randomness);
+        player.set_hash_size(self.hash_size_mb);
+        player
+    }
+
+    // The definition as it's written in the config file, with every setting that isn't the default.
+    pub fn format(&self) -> String {
+        let default = AiDefinition::new(&self.name, self.weights, DEFAULT_PIECE_VALUES);
+        let scorers = SCORER_NAMES.iter().zip(self.weights.iter())
+            .filter(|&(_, &weight)| weight != 0)
+            .map(|(name, weight)| format!("{} = {}", name, weight))
+            .collect::<Vec<_>>();
+        let mut result = format!("[[ai]]\nname = \"{}\"\nscorers = {{ {} }}\n",
+            escape(&self.name), scorers.join(", "));
+        if self.piece_values != default.piece_values {
+            result.push_str(&format!("piece_values = [{}]\n",
+                self.piece_values.iter().map(|value| value.to_string()).collect::<Vec<_>>().join(", ")));
+        }
+
+        if let Some(depth) = self.depth {
+            result.push_str(&format!("depth = {}\n", depth));
+        }
+
+        if let Some(movetime) = self.movetime {
+            result.push_str(&format!("movetime = {}\n",
+                movetime.as_secs() as f64 + movetime.subsec_nanos() as f64 / 1e9));
+        }
+
+        if self.quiescence != default.quiescence {
+            result.push_str(&format!("quiescence = {}\n", self.quiescence));
+        }
+
+        if self.hash_size_mb != default.hash_size_mb {
+            result.push_str(&format!("hash_mb = {}\n", self.hash_size_mb));
+        }
+
+        if self.randomness != default.randomness {
+            result.push_str(&format!("randomness = {}\n", self.randomness));
+        }
+
+        result
+    }
+}
+
+pub fn builtin_definitions() -> Vec<AiDefinition> {
+    EVALUATOR_NAMES.iter().map(|name| {
+        AiDefinition::new(name, computer_player::evaluator_weights(name).unwrap(), DEFAULT_PIECE_VALUES)
+    }).collect()
+}
+
+// The AIs declared in the config file, or the built in ones if there isn't one.
+pub fn load_definitions(path: &str) -> Result<Vec<AiDefinition>, String> {
+    let mut text = String::new();
+    match File::open(path) {
+        Ok(mut file) => file.read_to_string(&mut text)
+            .map_err(|error| format!("Failed to read {}: {}", path, error))?,
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => return Ok(builtin_definitions()),
+        Err(error) => return Err(format!("Failed to open {}: {}", path, error)),
+    };
+
+    parse_definitions(&text).map_err(|error| format!("Invalid AI config {}: {}", path, error))
+}
+
+// Adds an AI to the end of the config file, starting the file with the built in AIs if there isn't
+// one yet so that they aren't lost.
+pub fn add_definition(path: &str, definition: &AiDefinition) -> Result<(), String> {
+    let definitions = load_definitions(path)?;
+    if definitions.iter().any(|existing| existing.name == definition.name) {
+        return Err(format!("There is already an AI called {}", definition.name));
+    }
+
+    let is_new = File::open(path).is_err();
+    let mut text = String::new();
+    if is_new {
+        for existing in definitions.iter() {
+            text.push_str(&existing.format());
+            text.push('\n');
+        }
+    }
+
+    text.push_str(&definition.format());
+    OpenOptions::new().create(true).append(true).open(path)
+        .and_then(|mut file| write!(file, "{}{}", if is_new { "" } else { "\n" }, text))
+        .map_err(|error| format!("Failed to write {}: {}", path, error))
+}
+
+pub fn parse_definitions(text: &str) -> Result<Vec<AiDefinition>, String> {
+    let mut tables: Vec<Vec<(String, Value)>> = vec![];
+    for (line_index, line) in text.lines().enumerate() {
+        let error = |message: &str| format!("line {}: {}", line_index + 1, message);
+        let mut chars = line.chars().peekable();
+        skip_whitespace(&mut chars);
+        match chars.peek() {
+            None | Some(&'#') => continue,
+            Some(&'[') => {
+                let header = line.trim().splitn(2, '#').next().unwrap().trim();
+                if header != "[[ai]]" {
+                    return Err(error(&format!("expected [[ai]] but found {}", header)));
+                }
+
+                tables.push(vec![]);
+            },
+            Some(_) => {
+                let key = chars.by_ref().take_while(|&c| c != '=').collect::<String>().trim().to_owned();
+                let value = parse_value(&mut chars).map_err(|message| error(&message))?;
+                skip_whitespace(&mut chars);
+                if chars.peek().map_or(false, |&c| c != '#') {
+                    return Err(error("unexpected text after the value"));
+                }
+
+                match tables.last_mut() {
+                    Some(table) => table.push((key, value)),
+                    None => return Err(error("settings must follow an [[ai]] header")),
+                }
+            },
+        }
+    }
+
+    if tables.is_empty() {
+        return Err("no AIs are declared".to_owned());
+    }
+
+    let mut definitions: Vec<AiDefinition> = vec![];
+    for table in tables {
+        let definition = build_definition(table)?;
+        if definitions.iter().any(|existing| existing.name == definition.name) {
+            return Err(format!("there is more than one AI called {}", definition.name));
+        }
+
+        definitions.push(definition);
+    }
+
+    Ok(definitions)
+}
+
+fn build_definition(table: Vec<(String, Value)>) -> Result<AiDefinition, String> {
+    let name = match table.iter().find(|&&(ref key, _)| key == "name") {
+        Some(&(_, Value::String(ref name))) if !name.trim().is_empty() => name.clone(),
+        _ => return Err("every AI needs a name".to_owned()),
+    };
+    let invalid = |key: &str| format!("invalid {} for {}", key, name);
+
+    let mut definition = AiDefinition::new(&name, [0; 3], DEFAULT_PIECE_VALUES);
+    let mut has_scorers = false;
+    for (key, value) in table {
+        match (key.as_str(), value) {
+            ("name", _) => (),
+            ("scorers", Value::Table(scorers)) => {
+                for (scorer, weight) in scorers {
+                    let index = SCORER_NAMES.iter().position(|&name| name == scorer)
+                        .ok_or(format!("unknown scorer {} for {}", scorer, name))?;
+                    definition.weights[index] = weight.as_i16().ok_or(invalid("scorers"))?;
+                }
+
+                has_scorers = true;
+            },
+            ("piece_values", Value::Array(ref values)) if values.len() == definition.piece_values.len() => {
+                for (piece_value, value) in definition.piece_values.iter_mut().zip(values.iter()) {
+                    *piece_value = value.as_i16().ok_or(invalid("piece_values"))?;
+                }
+            },
+            ("depth", Value::Integer(depth)) if depth > 0 && depth <= computer_player::MAX_DEPTH as i64 =>
+                definition.depth = Some(depth as u8),
+            ("movetime", ref value) if value.as_movetime().is_some() => definition.movetime = value.as_movetime(),
+            ("quiescence", Value::Boolean(quiescence)) => definition.quiescence = quiescence,
+            ("hash_mb", Value::Integer(size_mb))
+                    if size_mb > 0 && size_mb <= transposition_table::MAX_SIZE_MB as i64 =>
+                definition.hash_size_mb = size_mb as usize,
+            ("randomness", Value::Boolean(randomness)) => definition.randomness = randomness,
+            ("scorers", _) | ("piece_values", _) | ("depth", _) | ("movetime", _) | ("quiescence", _)
+                | ("hash_mb", _) | ("randomness", _) => return Err(invalid(&key)),
+            _ => return Err(format!("unknown setting {} for {}", key, name)),
+        }
+    }
+
+    if !has_scorers {
+        return Err(format!("{} needs scorers", name));
+    }
+
+    Ok(definition)
+}
+
+// The kinds of TOML value that AI definitions use. Tables are only written inline.
+#[derive(Debug)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    Table(Vec<(String, Value)>),
+}
+
+impl Value {
+    fn as_integer(&self) -> Option<i64> {
+        match *self {
+            Value::Integer(integer) => Some(integer),
+            _ => None,
+        }
+    }
+
+    // Weights and piece values have to fit in an i16.
+    fn as_i16(&self) -> Option<i16> {
+        self.as_integer()
+            .filter(|&integer| integer >= i16::MIN as i64 && integer <= i16::MAX as i64)
+            .map(|integer| integer as i16)
+    }
+
+    fn as_float(&self) -> Option<f64> {
+        match *self {
+            Value::Integer(integer) => Some(integer as f64),
+            Value::Float(float) => Some(float),
+            _ => None,
+        }
+    }
+
+    // A time in seconds, which has to be more than 0.
+    fn as_movetime(&self) -> Option<Duration> {
+        self.as_float().filter(|&seconds| seconds > 0.0).and_then(clock::duration_from_seconds)
+    }
+}
+
+fn parse_value(chars: &mut Peekable<Chars>) -> Result<Value, String> {
+    skip_whitespace(chars);
+    match chars.peek().cloned() {
+        Some('"') => {
+            chars.next();
+            let mut string = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => return Ok(Value::String(string)),
+                    Some('\\') => match chars.next() {
+                        Some('"') => string.push('"'),
+                        Some('\\') => string.push('\\'),
+                        _ => return Err("unsupported escape in string".to_owned()),
+                    },
+                    Some(c) => string.push(c),
+                    None => return Err("unterminated string".to_owned()),
+                }
+            }
+        },
+        Some('[') => {
+            chars.next();
+            parse_list(chars, ']', |chars| parse_value(chars)).map(Value::Array)
+        },
+        Some('{') => {
+            chars.next();
+            parse_list(chars, '}', |chars| {
+                let key = chars.by_ref().take_while(|&c| c != '=').collect::<String>().trim().to_owned();
+                parse_value(chars).map(|value| (key, value))
+            }).map(Value::Table)
+        },
+        Some(_) => {
+            let mut token = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || ",]}#".contains(c) {
+                    break;
+                }
+
+                token.push(c);
+                chars.next();
+            }
+
+            match token.as_str() {
+                "true" => Ok(Value::Boolean(true)),
+                "false" => Ok(Value::Boolean(false)),
+                _ => token.parse().map(Value::Integer)
+                    .or_else(|_| token.parse().map(Value::Float))
+                    .map_err(|_| format!("invalid value {}", token)),
+            }
+        },
+        None => Err("missing value".to_owned()),
+    }
+}
+
+// Parses comma separated items up to the closing character, which has to be on the same line.
+fn parse_list<T, F>(chars: &mut Peekable<Chars>, close: char, mut parse_item: F) -> Result<Vec<T>, String>
+        where F: FnMut(&mut Peekable<Chars>) -> Result<T, String> {
+
+    let mut items = vec![];
+    loop {
+        skip_whitespace(chars);
+        if chars.peek() == Some(&close) {
+            chars.next();
+            return Ok(items);
+        }
+
+        items.push(parse_item(chars)?);
+        skip_whitespace(chars);
+        match chars.next() {
+            Some(',') => (),
+            Some(c) if c == close => return Ok(items),
+            _ => return Err(format!("expected , or {}", close)),
+        }
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.peek().map_or(false, |c| c.is_whitespace()) {
+        chars.next();
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(text: &str) -> String {
+        parse_definitions(text).err().expect(text)
+    }
+
+    #[test]
+    fn parse_config() {
+        let definitions = parse_definitions(r#"
+# The AIs to play with.
+[[ai]]
+name = "fast"  # Searches briefly.
+scorers = { piece = 70, moves = 1 }
+movetime = 0.5
+
+[[ai]]
+name = "deep \"one\""
+scorers = {piece=20,spaces=3}
+piece_values = [1, 3, 4, 5, 10]
+depth = 6
+movetime = 2
+quiescence = false
+hash_mb = 64
+randomness = false
+"#).unwrap();
+
+        assert_eq!(definitions.len(), 2);
+        let fast = &definitions[0];
+        assert_eq!(fast.name, "fast");
+        assert_eq!(fast.weights, [70, 0, 1]);
+        assert_eq!(fast.piece_values, DEFAULT_PIECE_VALUES);
+        assert_eq!(fast.movetime, Some(Duration::from_millis(500)));
+        assert_eq!(fast.depth, None);
+
+        let deep = &definitions[1];
+        assert_eq!(deep.name, "deep \"one\"");
+        assert_eq!(deep.weights, [20, 3, 0]);
+        assert_eq!(deep.piece_values, [1, 3, 4, 5, 10]);
+        assert_eq!(deep.depth, Some(6));
+        assert_eq!(deep.movetime, Some(Duration::from_secs(2)));
+        assert!(!deep.quiescence);
+        assert_eq!(deep.hash_size_mb, 64);
+        assert!(!deep.randomness);
+    }
+
+    #[test]
+    fn format_round_trip() {
+        let builtins = builtin_definitions();
+        let text = builtins.iter().map(|definition| definition.format()).collect::<Vec<_>>().join("\n");
+        assert_eq!(parse_definitions(&text), Ok(builtins));
+
+        let mut definition = AiDefinition::new("every \"setting\"", [-3, 0, 2], [2, 5, 6, 9, 17]);
+        definition.depth = Some(5);
+        definition.movetime = Some(Duration::from_millis(1250));
+        definition.quiescence = false;
+        definition.hash_size_mb = 1;
+        definition.randomness = false;
+        assert_eq!(parse_definitions(&definition.format()), Ok(vec![definition]));
+    }
+
+    #[test]
+    fn invalid_configs() {
+        assert_eq!(parse_error(""), "no AIs are declared");
+        assert_eq!(parse_error("name = \"a\""), "line 1: settings must follow an [[ai]] header");
+        assert_eq!(parse_error("[ai]"), "line 1: expected [[ai]] but found [ai]");
+        assert_eq!(parse_error("[[ai]]\nname = \"a\"\nscorers = { piece = 1 }\n\
+            [[ai]]\nname = \"a\"\nscorers = { moves = 1 }"), "there is more than one AI called a");
+        assert_eq!(parse_error("[[ai]]\nname = \"a\"\nscorers = { piece = 1 }\nspeed = 3"),
+            "unknown setting speed for a");
+        assert_eq!(parse_error("[[ai]]\nname = \"a\"\nscorers = { pieces = 1 }"), "unknown scorer pieces for a");
+        assert_eq!(parse_error("[[ai]]\nname = \"a\""), "a needs scorers");
+        assert_eq!(parse_error("[[ai]]\nscorers = { piece = 1 }"), "every AI needs a name");
+        assert_eq!(parse_error("[[ai]]\nname = \"a\"\nscorers = { piece = 1 "), "line 3: expected , or }");
+        assert_eq!(parse_error("[[ai]]\nname = \"a\" b"), "line 2: unexpected text after the value");
+        assert_eq!(parse_error("[[ai]]\nname = \"a"), "line 2: unterminated string");
+    }
+
+    #[test]
+    fn out_of_range_settings() {
+        for &(setting, value) in [
+            ("scorers", "{ piece = 40000 }"),
+            ("piece_values", "[1, 3, 3, 5]"),
+            ("piece_values", "[1, 3, 3, 5, -40000]"),
+            ("depth", "0"),
+            ("depth", "65"),
+            ("depth", "2.5"),
+            ("movetime", "0"),
+            ("movetime", "-1"),
+            ("movetime", "inf"),
+            ("movetime", "nan"),
+            ("movetime", "1e30"),
+            ("hash_mb", "0"),
+            ("hash_mb", "5000"),
+            ("quiescence", "1"),
+        ].iter() {
+            let text = format!("[[ai]]\nname = \"a\"\nscorers = {{ piece = 1 }}\n{} = {}", setting, value);
+            assert_eq!(parse_definitions(&text), Err(format!("invalid {} for a", setting)), "{}", text);
+        }
+    }
+}
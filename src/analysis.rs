@@ -0,0 +1,108 @@
+use std::fs::File;
+use std::io::Read;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+
+use ai_config;
+use cli::Arguments;
+use computer_player;
+use computer_player::SearchLimits;
+use game_state::GameState;
+use game_state::PlayerState;
+use game_state::STARTING_FEN;
+use pgn;
+use transposition_table::TranspositionTable;
+
+const DEFAULT_PLAYER: &'static str = "spaces_moves";
+
+// Searches a position with one of the AIs, printing the best line found at each depth. The position
+// is either a FEN, or a position in a game from a PGN file.
+pub fn run_analysis(arguments: &Arguments, limits: SearchLimits) -> Result<(), String> {
+    let game_state = read_position(arguments)?;
+    let name = arguments.value("player").unwrap_or(DEFAULT_PLAYER);
+    let definitions = ai_config::load_definitions(ai_config::AI_CONFIG_PATH).unwrap_or_else(|error| {
+        println!("{}. Using the built in AIs instead.", error);
+        vec![]
+    });
+    // The built in AIs can be analysed with even when the config file leaves them out.
+    let definition = definitions.into_iter()
+        .chain(ai_config::builtin_definitions())
+        .find(|definition| definition.name == name)
+        .ok_or(format!("Unknown AI: {}", name))?;
+    // The AI's own limits are only used when none are given.
+    let limits = if limits.depth.is_none() && limits.movetime.is_none() {
+        SearchLimits { depth: definition.depth, movetime: definition.movetime, .. limits }
+    } else {
+        limits
+    };
+
+    println!("{}", game_state.format());
+    println!("{}", game_state.format_fen());
+    let moves = match game_state.get_player_moves() {
+        PlayerState::CanMove(moves) => moves,
+        PlayerState::Checkmate => {
+            println!("Checkmate, so there is nothing to search.");
+            return Ok(());
+        },
+        PlayerState::Stalemate => {
+            println!("Stalemate, so there is nothing to search.");
+            return Ok(());
+        },
+        PlayerState::Draw(draw_reason) => {
+            println!("Drawn by {:?}, so there is nothing to search.", draw_reason);
+            return Ok(());
+        },
+    };
+
+    let mut table = TranspositionTable::new(definition.hash_size_mb);
+    let iteration = computer_player::iterative_deepening(
+        &game_state, &moves, &definition.evaluator(), &limits, &mut table, Arc::new(AtomicBool::new(false)),
+        &mut |iteration| {
+            let score = iteration.move_scores[0].1;
+            let score = match computer_player::moves_to_mate(score) {
+                Some(moves_to_mate) => format!("mate {}", moves_to_mate),
+                None => score.to_string(),
+            };
+            println!("Depth {} ({:.2}s), score {}: {}",
+                iteration.depth,
+                iteration.elapsed.as_secs() as f64 + iteration.elapsed.subsec_nanos() as f64 / 1e9,
+                score,
+                pgn::format_line(&game_state, &iteration.principal_variation));
+        });
+
+    println!("{}", table.format_stats());
+    println!("Best move: {}", iteration.move_scores[0].0.san_format(&game_state));
+    Ok(())
+}
+
+// Games and plies are counted from one, and the position defaults to the end of the game.
+fn read_position(arguments: &Arguments) -> Result<GameState, String> {
+    let path = match arguments.value("pgn") {
+        Some(path) => path,
+        None => {
+            let fen = arguments.value("fen").unwrap_or(STARTING_FEN);
+            return GameState::from_fen(fen).ok_or(format!("Invalid FEN: {}", fen));
+        },
+    };
+    if arguments.is_set("fen") {
+        return Err("Only one of --fen and --pgn can be given".to_owned());
+    }
+
+    let mut text = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut text))
+        .map_err(|error| format!("Failed to read {}: {}", path, error))?;
+
+    let game_number: usize = arguments.parse("game")?.unwrap_or(1);
+    let pgn_game = match pgn::parse_pgn(&text).into_iter().nth(game_number.wrapping_sub(1)) {
+        Some(pgn_game) => pgn_game.map_err(|error| format!("Game {} in {}: {}", game_number, path, error))?,
+        None => return Err(format!("{} has no game {}", path, game_number)),
+    };
+
+    let ply = arguments.parse("ply")?.unwrap_or(pgn_game.moves.len());
+    if ply > pgn_game.moves.len() {
+        return Err(format!("Game {} only has {} plies", game_number, pgn_game.moves.len()));
+    }
+
+    Ok(pgn_game.replay(ply))
+}
@@ -0,0 +1,335 @@
+use game_state::Color;
+use position::Position;
+
+// A set of squares, with bit n set for square n. Squares are numbered from 0 for a1 to 63 for h8,
+// going along each row before moving up to the next.
+pub type Bitboard = u64;
+
+// The ray tables are ordered so that the directions leading towards higher numbered squares come
+// first, which determines which end of a blocked ray the nearest blocker is at.
+const POSITIVE_RAY_COUNT: usize = 4;
+const NORTH: usize = 0;
+const EAST: usize = 1;
+const NORTH_EAST: usize = 2;
+const NORTH_WEST: usize = 3;
+const SOUTH: usize = 4;
+const WEST: usize = 5;
+const SOUTH_WEST: usize = 6;
+const SOUTH_EAST: usize = 7;
+
+pub fn square(position: &Position) -> usize {
+    (position.row * 8 + position.column) as usize
+}
+
+pub fn position(square: usize) -> Position {
+    Position { column: (square % 8) as i8, row: (square / 8) as i8 }
+}
+
+pub fn bit(position: &Position) -> Bitboard {
+    1 << square(position)
+}
+
+// Iterates over the squares in a set from lowest to highest.
+pub fn squares(bitboard: Bitboard) -> Squares {
+    Squares { remaining: bitboard }
+}
+
+pub struct Squares {
+    remaining: Bitboard,
+}
+
+impl Iterator for Squares {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let square = self.remaining.trailing_zeros() as usize;
+        // Clears the lowest set bit.
+        self.remaining &= self.remaining - 1;
+        Some(square)
+    }
+}
+
+pub fn knight_attacks(square: usize) -> Bitboard {
+    KNIGHT_ATTACKS[square]
+}
+
+pub fn king_attacks(square: usize) -> Bitboard {
+    KING_ATTACKS[square]
+}
+
+// The squares that a pawn of the specified color on the square could capture on.
+pub fn pawn_attacks(color: Color, square: usize) -> Bitboard {
+    PAWN_ATTACKS[color.index()][square]
+}
+
+pub fn bishop_attacks(square: usize, occupied: Bitboard) -> Bitboard {
+    [NORTH_EAST, NORTH_WEST, SOUTH_WEST, SOUTH_EAST].iter()
+        .fold(0, |attacks, &direction| attacks | ray_attacks(direction, square, occupied))
+}
+
+pub fn rook_attacks(square: usize, occupied: Bitboard) -> Bitboard {
+    [NORTH, EAST, SOUTH, WEST].iter()
+        .fold(0, |attacks, &direction| attacks | ray_attacks(direction, square, occupied))
+}
+
+pub fn queen_attacks(square: usize, occupied: Bitboard) -> Bitboard {
+    bishop_attacks(square, occupied) | rook_attacks(square, occupied)
+}
+
+// The squares along a ray up to and including the first occupied one. The squares beyond the
+// blocker are exactly the blocker's own ray in the same direction, so they can be removed with it.
+fn ray_attacks(direction: usize, square: usize, occupied: Bitboard) -> Bitboard {
+    let ray = RAYS[direction][square];
+    let blockers = ray & occupied;
+    if blockers == 0 {
+        return ray;
+    }
+
+    let blocker = if direction < POSITIVE_RAY_COUNT {
+        blockers.trailing_zeros()
+    } else {
+        63 - blockers.leading_zeros()
+    };
+
+    ray ^ RAYS[direction][blocker as usize]
+}
+
+// Generated by stepping out from every square by each of the piece's offsets, stopping at the edge
+// of the board.
+static KNIGHT_ATTACKS: [u64; 64] = [
+    0x0000000000020400, 0x0000000000050800, 0x00000000000A1100, 0x0000000000142200,
+    0x0000000000284400, 0x0000000000508800, 0x0000000000A01000, 0x0000000000402000,
+    0x0000000002040004, 0x0000000005080008, 0x000000000A110011, 0x0000000014220022,
+    0x0000000028440044, 0x0000000050880088, 0x00000000A0100010, 0x0000000040200020,
+    0x0000000204000402, 0x0000000508000805, 0x0000000A1100110A, 0x0000001422002214,
+    0x0000002844004428, 0x0000005088008850, 0x000000A0100010A0, 0x0000004020002040,
+    0x0000020400040200, 0x0000050800080500, 0x00000A1100110A00, 0x0000142200221400,
+    0x0000284400442800, 0x0000508800885000, 0x0000A0100010A000, 0x0000402000204000,
+    0x0002040004020000, 0x0005080008050000, 0x000A1100110A0000, 0x0014220022140000,
+    0x0028440044280000, 0x0050880088500000, 0x00A0100010A00000, 0x0040200020400000,
+    0x0204000402000000, 0x0508000805000000, 0x0A1100110A000000, 0x1422002214000000,
+    0x2844004428000000, 0x5088008850000000, 0xA0100010A0000000, 0x4020002040000000,
+    0x0400040200000000, 0x0800080500000000, 0x1100110A00000000, 0x2200221400000000,
+    0x4400442800000000, 0x8800885000000000, 0x100010A000000000, 0x2000204000000000,
+    0x0004020000000000, 0x0008050000000000, 0x00110A0000000000, 0x0022140000000000,
+    0x0044280000000000, 0x0088500000000000, 0x0010A00000000000, 0x0020400000000000,
+];
+
+static KING_ATTACKS: [u64; 64] = [
+    0x0000000000000302, 0x0000000000000705, 0x0000000000000E0A, 0x0000000000001C14,
+    0x0000000000003828, 0x0000000000007050, 0x000000000000E0A0, 0x000000000000C040,
+    0x0000000000030203, 0x0000000000070507, 0x00000000000E0A0E, 0x00000000001C141C,
+    0x0000000000382838, 0x0000000000705070, 0x0000000000E0A0E0, 0x0000000000C040C0,
+    0x0000000003020300, 0x0000000007050700, 0x000000000E0A0E00, 0x000000001C141C00,
+    0x0000000038283800, 0x0000000070507000, 0x00000000E0A0E000, 0x00000000C040C000,
+    0x0000000302030000, 0x0000000705070000, 0x0000000E0A0E0000, 0x0000001C141C0000,
+    0x0000003828380000, 0x0000007050700000, 0x000000E0A0E00000, 0x000000C040C00000,
+    0x0000030203000000, 0x0000070507000000, 0x00000E0A0E000000, 0x00001C141C000000,
+    0x0000382838000000, 0x0000705070000000, 0x0000E0A0E0000000, 0x0000C040C0000000,
+    0x0003020300000000, 0x0007050700000000, 0x000E0A0E00000000, 0x001C141C00000000,
+    0x0038283800000000, 0x0070507000000000, 0x00E0A0E000000000, 0x00C040C000000000,
+    0x0302030000000000, 0x0705070000000000, 0x0E0A0E0000000000, 0x1C141C0000000000,
+    0x3828380000000000, 0x7050700000000000, 0xE0A0E00000000000, 0xC040C00000000000,
+    0x0203000000000000, 0x0507000000000000, 0x0A0E000000000000, 0x141C000000000000,
+    0x2838000000000000, 0x5070000000000000, 0xA0E0000000000000, 0x40C0000000000000,
+];
+
+static PAWN_ATTACKS: [[u64; 64]; 2] = [
+    // White
+    [
+        0x0000000000000200, 0x0000000000000500, 0x0000000000000A00, 0x0000000000001400,
+        0x0000000000002800, 0x0000000000005000, 0x000000000000A000, 0x0000000000004000,
+        0x0000000000020000, 0x0000000000050000, 0x00000000000A0000, 0x0000000000140000,
+        0x0000000000280000, 0x0000000000500000, 0x0000000000A00000, 0x0000000000400000,
+        0x0000000002000000, 0x0000000005000000, 0x000000000A000000, 0x0000000014000000,
+        0x0000000028000000, 0x0000000050000000, 0x00000000A0000000, 0x0000000040000000,
+        0x0000000200000000, 0x0000000500000000, 0x0000000A00000000, 0x0000001400000000,
+        0x0000002800000000, 0x0000005000000000, 0x000000A000000000, 0x0000004000000000,
+        0x0000020000000000, 0x0000050000000000, 0x00000A0000000000, 0x0000140000000000,
+        0x0000280000000000, 0x0000500000000000, 0x0000A00000000000, 0x0000400000000000,
+        0x0002000000000000, 0x0005000000000000, 0x000A000000000000, 0x0014000000000000,
+        0x0028000000000000, 0x0050000000000000, 0x00A0000000000000, 0x0040000000000000,
+        0x0200000000000000, 0x0500000000000000, 0x0A00000000000000, 0x1400000000000000,
+        0x2800000000000000, 0x5000000000000000, 0xA000000000000000, 0x4000000000000000,
+        0x0000000000000000, 0x0000000000000000, 0x0000000000000000, 0x0000000000000000,
+        0x0000000000000000, 0x0000000000000000, 0x0000000000000000, 0x0000000000000000,
+    ],
+    // Black
+    [
+        0x0000000000000000, 0x0000000000000000, 0x0000000000000000, 0x0000000000000000,
+        0x0000000000000000, 0x0000000000000000, 0x0000000000000000, 0x0000000000000000,
+        0x0000000000000002, 0x0000000000000005, 0x000000000000000A, 0x0000000000000014,
+        0x0000000000000028, 0x0000000000000050, 0x00000000000000A0, 0x0000000000000040,
+        0x0000000000000200, 0x0000000000000500, 0x0000000000000A00, 0x0000000000001400,
+        0x0000000000002800, 0x0000000000005000, 0x000000000000A000, 0x0000000000004000,
+        0x0000000000020000, 0x0000000000050000, 0x00000000000A0000, 0x0000000000140000,
+        0x0000000000280000, 0x0000000000500000, 0x0000000000A00000, 0x0000000000400000,
+        0x0000000002000000, 0x0000000005000000, 0x000000000A000000, 0x0000000014000000,
+        0x0000000028000000, 0x0000000050000000, 0x00000000A0000000, 0x0000000040000000,
+        0x0000000200000000, 0x0000000500000000, 0x0000000A00000000, 0x0000001400000000,
+        0x0000002800000000, 0x0000005000000000, 0x000000A000000000, 0x0000004000000000,
+        0x0000020000000000, 0x0000050000000000, 0x00000A0000000000, 0x0000140000000000,
+        0x0000280000000000, 0x0000500000000000, 0x0000A00000000000, 0x0000400000000000,
+        0x0002000000000000, 0x0005000000000000, 0x000A000000000000, 0x0014000000000000,
+        0x0028000000000000, 0x0050000000000000, 0x00A0000000000000, 0x0040000000000000,
+    ],
+];
+
+static RAYS: [[u64; 64]; 8] = [
+    // North
+    [
+        0x0101010101010100, 0x0202020202020200, 0x0404040404040400, 0x0808080808080800,
+        0x1010101010101000, 0x2020202020202000, 0x4040404040404000, 0x8080808080808000,
+        0x0101010101010000, 0x0202020202020000, 0x0404040404040000, 0x0808080808080000,
+        0x1010101010100000, 0x2020202020200000, 0x4040404040400000, 0x8080808080800000,
+        0x0101010101000000, 0x0202020202000000, 0x0404040404000000, 0x0808080808000000,
+        0x1010101010000000, 0x2020202020000000, 0x4040404040000000, 0x8080808080000000,
+        0x0101010100000000, 0x0202020200000000, 0x0404040400000000, 0x0808080800000000,
+        0x1010101000000000, 0x2020202000000000, 0x4040404000000000, 0x8080808000000000,
+        0x0101010000000000, 0x0202020000000000, 0x0404040000000000, 0x0808080000000000,
+        0x1010100000000000, 0x2020200000000000, 0x4040400000000000, 0x8080800000000000,
+        0x0101000000000000, 0x0202000000000000, 0x0404000000000000, 0x0808000000000000,
+        0x1010000000000000, 0x2020000000000000, 0x4040000000000000, 0x8080000000000000,
+        0x0100000000000000, 0x0200000000000000, 0x0400000000000000, 0x0800000000000000,
+        0x1000000000000000, 0x2000000000000000, 0x4000000000000000, 0x8000000000000000,
+        0x0000000000000000, 0x0000000000000000, 0x0000000000000000, 0x0000000000000000,
+        0x0000000000000000, 0x0000000000000000, 0x0000000000000000, 0x0000000000000000,
+    ],
+    // East
+    [
+        0x00000000000000FE, 0x00000000000000FC, 0x00000000000000F8, 0x00000000000000F0,
+        0x00000000000000E0, 0x00000000000000C0, 0x0000000000000080, 0x0000000000000000,
+        0x000000000000FE00, 0x000000000000FC00, 0x000000000000F800, 0x000000000000F000,
+        0x000000000000E000, 0x000000000000C000, 0x0000000000008000, 0x0000000000000000,
+        0x0000000000FE0000, 0x0000000000FC0000, 0x0000000000F80000, 0x0000000000F00000,
+        0x0000000000E00000, 0x0000000000C00000, 0x0000000000800000, 0x0000000000000000,
+        0x00000000FE000000, 0x00000000FC000000, 0x00000000F8000000, 0x00000000F0000000,
+        0x00000000E0000000, 0x00000000C0000000, 0x0000000080000000, 0x0000000000000000,
+        0x000000FE00000000, 0x000000FC00000000, 0x000000F800000000, 0x000000F000000000,
+        0x000000E000000000, 0x000000C000000000, 0x0000008000000000, 0x0000000000000000,
+        0x0000FE0000000000, 0x0000FC0000000000, 0x0000F80000000000, 0x0000F00000000000,
+        0x0000E00000000000, 0x0000C00000000000, 0x0000800000000000, 0x0000000000000000,
+        0x00FE000000000000, 0x00FC000000000000, 0x00F8000000000000, 0x00F0000000000000,
+        0x00E0000000000000, 0x00C0000000000000, 0x0080000000000000, 0x0000000000000000,
+        0xFE00000000000000, 0xFC00000000000000, 0xF800000000000000, 0xF000000000000000,
+        0xE000000000000000, 0xC000000000000000, 0x8000000000000000, 0x0000000000000000,
+    ],
+    // North east
+    [
+        0x8040201008040200, 0x0080402010080400, 0x0000804020100800, 0x0000008040201000,
+        0x0000000080402000, 0x0000000000804000, 0x0000000000008000, 0x0000000000000000,
+        0x4020100804020000, 0x8040201008040000, 0x0080402010080000, 0x0000804020100000,
+        0x0000008040200000, 0x0000000080400000, 0x0000000000800000, 0x0000000000000000,
+        0x2010080402000000, 0x4020100804000000, 0x8040201008000000, 0x0080402010000000,
+        0x0000804020000000, 0x0000008040000000, 0x0000000080000000, 0x0000000000000000,
+        0x1008040200000000, 0x2010080400000000, 0x4020100800000000, 0x8040201000000000,
+        0x0080402000000000, 0x0000804000000000, 0x0000008000000000, 0x0000000000000000,
+        0x0804020000000000, 0x1008040000000000, 0x2010080000000000, 0x4020100000000000,
+        0x8040200000000000, 0x0080400000000000, 0x0000800000000000, 0x0000000000000000,
+        0x0402000000000000, 0x0804000000000000, 0x1008000000000000, 0x2010000000000000,
+        0x4020000000000000, 0x8040000000000000, 0x0080000000000000, 0x0000000000000000,
+        0x0200000000000000, 0x0400000000000000, 0x0800000000000000, 0x1000000000000000,
+        0x2000000000000000, 0x4000000000000000, 0x8000000000000000, 0x0000000000000000,
+        0x0000000000000000, 0x0000000000000000, 0x0000000000000000, 0x0000000000000000,
+        0x0000000000000000, 0x0000000000000000, 0x0000000000000000, 0x0000000000000000,
+    ],
+    // North west
+    [
+        0x0000000000000000, 0x0000000000000100, 0x0000000000010200, 0x0000000001020400,
+        0x0000000102040800, 0x0000010204081000, 0x0001020408102000, 0x0102040810204000,
+        0x0000000000000000, 0x0000000000010000, 0x0000000001020000, 0x0000000102040000,
+        0x0000010204080000, 0x0001020408100000, 0x0102040810200000, 0x0204081020400000,
+        0x0000000000000000, 0x0000000001000000, 0x0000000102000000, 0x0000010204000000,
+        0x0001020408000000, 0x0102040810000000, 0x0204081020000000, 0x0408102040000000,
+        0x0000000000000000, 0x0000000100000000, 0x0000010200000000, 0x0001020400000000,
+        0x0102040800000000, 0x0204081000000000, 0x0408102000000000, 0x0810204000000000,
+        0x0000000000000000, 0x0000010000000000, 0x0001020000000000, 0x0102040000000000,
+        0x0204080000000000, 0x0408100000000000, 0x0810200000000000, 0x1020400000000000,
+        0x0000000000000000, 0x0001000000000000, 0x0102000000000000, 0x0204000000000000,
+        0x0408000000000000, 0x0810000000000000, 0x1020000000000000, 0x2040000000000000,
+        0x0000000000000000, 0x0100000000000000, 0x0200000000000000, 0x0400000000000000,
+        0x0800000000000000, 0x1000000000000000, 0x2000000000000000, 0x4000000000000000,
+        0x0000000000000000, 0x0000000000000000, 0x0000000000000000, 0x0000000000000000,
+        0x0000000000000000, 0x0000000000000000, 0x0000000000000000, 0x0000000000000000,
+    ],
+    // South
+    [
+        0x0000000000000000, 0x0000000000000000, 0x0000000000000000, 0x0000000000000000,
+        0x0000000000000000, 0x0000000000000000, 0x0000000000000000, 0x0000000000000000,
+        0x0000000000000001, 0x0000000000000002, 0x0000000000000004, 0x0000000000000008,
+        0x0000000000000010, 0x0000000000000020, 0x0000000000000040, 0x0000000000000080,
+        0x0000000000000101, 0x0000000000000202, 0x0000000000000404, 0x0000000000000808,
+        0x0000000000001010, 0x0000000000002020, 0x0000000000004040, 0x0000000000008080,
+        0x0000000000010101, 0x0000000000020202, 0x0000000000040404, 0x0000000000080808,
+        0x0000000000101010, 0x0000000000202020, 0x0000000000404040, 0x0000000000808080,
+        0x0000000001010101, 0x0000000002020202, 0x0000000004040404, 0x0000000008080808,
+        0x0000000010101010, 0x0000000020202020, 0x0000000040404040, 0x0000000080808080,
+        0x0000000101010101, 0x0000000202020202, 0x0000000404040404, 0x0000000808080808,
+        0x0000001010101010, 0x0000002020202020, 0x0000004040404040, 0x0000008080808080,
+        0x0000010101010101, 0x0000020202020202, 0x0000040404040404, 0x0000080808080808,
+        0x0000101010101010, 0x0000202020202020, 0x0000404040404040, 0x0000808080808080,
+        0x0001010101010101, 0x0002020202020202, 0x0004040404040404, 0x0008080808080808,
+        0x0010101010101010, 0x0020202020202020, 0x0040404040404040, 0x0080808080808080,
+    ],
+    // West
+    [
+        0x0000000000000000, 0x0000000000000001, 0x0000000000000003, 0x0000000000000007,
+        0x000000000000000F, 0x000000000000001F, 0x000000000000003F, 0x000000000000007F,
+        0x0000000000000000, 0x0000000000000100, 0x0000000000000300, 0x0000000000000700,
+        0x0000000000000F00, 0x0000000000001F00, 0x0000000000003F00, 0x0000000000007F00,
+        0x0000000000000000, 0x0000000000010000, 0x0000000000030000, 0x0000000000070000,
+        0x00000000000F0000, 0x00000000001F0000, 0x00000000003F0000, 0x00000000007F0000,
+        0x0000000000000000, 0x0000000001000000, 0x0000000003000000, 0x0000000007000000,
+        0x000000000F000000, 0x000000001F000000, 0x000000003F000000, 0x000000007F000000,
+        0x0000000000000000, 0x0000000100000000, 0x0000000300000000, 0x0000000700000000,
+        0x0000000F00000000, 0x0000001F00000000, 0x0000003F00000000, 0x0000007F00000000,
+        0x0000000000000000, 0x0000010000000000, 0x0000030000000000, 0x0000070000000000,
+        0x00000F0000000000, 0x00001F0000000000, 0x00003F0000000000, 0x00007F0000000000,
+        0x0000000000000000, 0x0001000000000000, 0x0003000000000000, 0x0007000000000000,
+        0x000F000000000000, 0x001F000000000000, 0x003F000000000000, 0x007F000000000000,
+        0x0000000000000000, 0x0100000000000000, 0x0300000000000000, 0x0700000000000000,
+        0x0F00000000000000, 0x1F00000000000000, 0x3F00000000000000, 0x7F00000000000000,
+    ],
+    // South west
+    [
+        0x0000000000000000, 0x0000000000000000, 0x0000000000000000, 0x0000000000000000,
+        0x0000000000000000, 0x0000000000000000, 0x0000000000000000, 0x0000000000000000,
+        0x0000000000000000, 0x0000000000000001, 0x0000000000000002, 0x0000000000000004,
+        0x0000000000000008, 0x0000000000000010, 0x0000000000000020, 0x0000000000000040,
+        0x0000000000000000, 0x0000000000000100, 0x0000000000000201, 0x0000000000000402,
+        0x0000000000000804, 0x0000000000001008, 0x0000000000002010, 0x0000000000004020,
+        0x0000000000000000, 0x0000000000010000, 0x0000000000020100, 0x0000000000040201,
+        0x0000000000080402, 0x0000000000100804, 0x0000000000201008, 0x0000000000402010,
+        0x0000000000000000, 0x0000000001000000, 0x0000000002010000, 0x0000000004020100,
+        0x0000000008040201, 0x0000000010080402, 0x0000000020100804, 0x0000000040201008,
+        0x0000000000000000, 0x0000000100000000, 0x0000000201000000, 0x0000000402010000,
+        0x0000000804020100, 0x0000001008040201, 0x0000002010080402, 0x0000004020100804,
+        0x0000000000000000, 0x0000010000000000, 0x0000020100000000, 0x0000040201000000,
+        0x0000080402010000, 0x0000100804020100, 0x0000201008040201, 0x0000402010080402,
+        0x0000000000000000, 0x0001000000000000, 0x0002010000000000, 0x0004020100000000,
+        0x0008040201000000, 0x0010080402010000, 0x0020100804020100, 0x0040201008040201,
+    ],
+    // South east
+    [
+        0x0000000000000000, 0x0000000000000000, 0x0000000000000000, 0x0000000000000000,
+        0x0000000000000000, 0x0000000000000000, 0x0000000000000000, 0x0000000000000000,
+        0x0000000000000002, 0x0000000000000004, 0x0000000000000008, 0x0000000000000010,
+        0x0000000000000020, 0x0000000000000040, 0x0000000000000080, 0x0000000000000000,
+        0x0000000000000204, 0x0000000000000408, 0x0000000000000810, 0x0000000000001020,
+        0x0000000000002040, 0x0000000000004080, 0x0000000000008000, 0x0000000000000000,
+        0x0000000000020408, 0x0000000000040810, 0x0000000000081020, 0x0000000000102040,
+        0x0000000000204080, 0x0000000000408000, 0x0000000000800000, 0x0000000000000000,
+        0x0000000002040810, 0x0000000004081020, 0x0000000008102040, 0x0000000010204080,
+        0x0000000020408000, 0x0000000040800000, 0x0000000080000000, 0x0000000000000000,
+        0x0000000204081020, 0x0000000408102040, 0x0000000810204080, 0x0000001020408000,
+        0x0000002040800000, 0x0000004080000000, 0x0000008000000000, 0x0000000000000000,
+        0x0000020408102040, 0x0000040810204080, 0x0000081020408000, 0x0000102040800000,
+        0x0000204080000000, 0x0000408000000000, 0x0000800000000000, 0x0000000000000000,
+        0x0002040810204080, 0x0004081020408000, 0x0008102040800000, 0x0010204080000000,
+        0x0020408000000000, 0x0040800000000000, 0x0080000000000000, 0x0000000000000000,
+    ],
+];
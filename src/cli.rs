@@ -0,0 +1,210 @@
+use std::str::FromStr;
+
+// A subcommand and the options that it accepts.
+struct CommandSpec {
+    name: &'static str,
+    summary: &'static str,
+    options: &'static [OptionSpec],
+}
+
+struct OptionSpec {
+    name: &'static str,
+    // What the option's value is, for the usage text. Options without one are flags.
+    value: Option<&'static str>,
+    help: &'static str,
+}
+
+const DEPTH: OptionSpec = OptionSpec {
+    name: "depth", value: Some("PLIES"), help: "Search depth for the AIs",
+};
+const MOVETIME: OptionSpec = OptionSpec {
+    name: "movetime", value: Some("SECONDS"), help: "Time per move for the AIs, such as 2.5",
+};
+const TIME_CONTROL: OptionSpec = OptionSpec {
+    name: "time-control", value: Some("CONTROL"),
+    help: "Clock for timed games, such as 300, 300+2, 300d2 or 40/5400",
+};
+const MOVE_LIMIT: OptionSpec = OptionSpec {
+    name: "move-limit", value: Some("50|75"), help: "Draw by the fifty-move or seventy-five-move rule. Defaults to 50",
+};
+const SEED: OptionSpec = OptionSpec {
+    name: "seed", value: Some("NUMBER"), help: "Seed for the AIs' random choices",
+};
+const PGN: OptionSpec = OptionSpec {
+    name: "pgn", value: Some("FILE"), help: "PGN file to append the games to",
+};
+const QUIET: OptionSpec = OptionSpec {
+    name: "quiet", value: None, help: "Only print the results",
+};
+const FEN: OptionSpec = OptionSpec {
+    name: "fen", value: Some("FEN"), help: "Starting position. Defaults to the opening position",
+};
+const SAVE_AS: OptionSpec = OptionSpec {
+    name: "save-as", value: Some("NAME"), help: "Add the tuned weights to the AI config under this name",
+};
+
+const COMMANDS: &'static [CommandSpec] = &[
+    CommandSpec {
+        name: "play",
+        summary: "Play a single game",
+        options: &[
+            OptionSpec { name: "white", value: Some("PLAYER"), help: "White player. Defaults to human" },
+            OptionSpec { name: "black", value: Some("PLAYER"), help: "Black player. Defaults to spaces_moves" },
+            FEN, DEPTH, MOVETIME, TIME_CONTROL, MOVE_LIMIT, SEED, PGN, QUIET,
+        ],
+    },
+    CommandSpec {
+        name: "tournament",
+        summary: "Play a round robin between AIs",
+        options: &[
+            OptionSpec {
+                name: "players", value: Some("A,B,..."), help: "AIs to play. Defaults to every AI",
+            },
+            OptionSpec { name: "rounds", value: Some("NUMBER"), help: "Rounds per match. Defaults to 1" },
+            DEPTH, MOVETIME, TIME_CONTROL, MOVE_LIMIT, SEED, PGN, QUIET,
+            OptionSpec { name: "report-text", value: Some("FILE"), help: "Write the results table to a file" },
+            OptionSpec { name: "report-csv", value: Some("FILE"), help: "Write the results as CSV" },
+            OptionSpec { name: "report-json", value: Some("FILE"), help: "Write the results as JSON" },
+        ],
+    },
+    CommandSpec {
+        name: "perft",
+        summary: "Count the legal move tree, broken down by root move",
+        options: &[
+            OptionSpec { name: "depth", value: Some("PLIES"), help: "Depth to count to. Required" },
+            FEN,
+        ],
+    },
+    CommandSpec {
+        name: "uci",
+        summary: "Speak the Universal Chess Interface. This is also the default without a command",
+        options: &[],
+    },
+    CommandSpec {
+        name: "analyze",
+        summary: "Search a position and print each depth's best line",
+        options: &[
+            OptionSpec { name: "player", value: Some("AI"), help: "AI to search with. Defaults to spaces_moves" },
+            FEN,
+            OptionSpec { name: "pgn", value: Some("FILE"), help: "PGN file to take the position from" },
+            OptionSpec { name: "game", value: Some("NUMBER"), help: "Game in the PGN file. Defaults to 1" },
+            OptionSpec {
+                name: "ply", value: Some("NUMBER"), help: "Moves into the game. Defaults to the end",
+            },
+            DEPTH, MOVETIME,
+        ],
+    },
+    CommandSpec {
+        name: "tune",
+        summary: "Evolve evaluation weights with a genetic algorithm",
+        options: &[
+            OptionSpec { name: "population", value: Some("NUMBER"), help: "Population size. Defaults to 8" },
+            OptionSpec { name: "generations", value: Some("NUMBER"), help: "Generations. Defaults to 10" },
+            OptionSpec { name: "rounds", value: Some("NUMBER"), help: "Rounds per generation. Defaults to 1" },
+            OptionSpec { name: "depth", value: Some("PLIES"), help: "Search depth. Defaults to 2" },
+            OptionSpec { name: "seed", value: Some("NUMBER"), help: "Seed for the run. Defaults to a random one" },
+            OptionSpec {
+                name: "checkpoint", value: Some("FILE"), help: "Written every generation, and resumed from",
+            },
+            SAVE_AS,
+        ],
+    },
+    CommandSpec {
+        name: "texel",
+        summary: "Fit evaluation weights to labelled positions",
+        options: &[
+            OptionSpec {
+                name: "positions", value: Some("FILE"),
+                help: "One position per line, as a FEN followed by 1-0, 0-1 or 1/2-1/2. Required",
+            },
+            OptionSpec { name: "start", value: Some("AI"), help: "AI to start from. Defaults to spaces_moves" },
+            SAVE_AS,
+        ],
+    },
+];
+
+// The command that the program was run with and the options passed to it, such as
+// "tournament --players piece_score,spaces_moves --rounds 2".
+pub struct Arguments {
+    pub command: String,
+    options: Vec<(String, Option<String>)>,
+}
+
+impl Arguments {
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.options.iter()
+            .find(|&&(ref option, _)| option == name)
+            .and_then(|&(_, ref value)| value.as_ref().map(|value| value.as_str()))
+    }
+
+    pub fn is_set(&self, name: &str) -> bool {
+        self.options.iter().any(|&(ref option, _)| option == name)
+    }
+
+    pub fn parse<T: FromStr>(&self, name: &str) -> Result<Option<T>, String> {
+        match self.value(name) {
+            Some(value) => value.parse().map(Some).map_err(|_| format!("Invalid --{}: {}", name, value)),
+            None => Ok(None),
+        }
+    }
+}
+
+// Options are written as "--name value" or "--name=value". Without a command, there is nothing to
+// parse and the program speaks UCI, since that's how chess GUIs run engines.
+pub fn parse_arguments(args: &[String]) -> Result<Arguments, String> {
+    let command_name = match args.first() {
+        Some(name) => name.as_str(),
+        None => return Ok(Arguments { command: "uci".to_owned(), options: vec![] }),
+    };
+    let command = match COMMANDS.iter().find(|command| command.name == command_name) {
+        Some(command) => command,
+        None if command_name == "help" || command_name == "--help" || command_name == "-h" =>
+            return Ok(Arguments { command: "help".to_owned(), options: vec![] }),
+        None => return Err(format!("Unknown command: {}", command_name)),
+    };
+
+    let mut options: Vec<(String, Option<String>)> = vec![];
+    let mut args = args[1..].iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            return Err(format!("Unexpected argument: {}", arg));
+        }
+
+        let mut parts = arg[2..].splitn(2, '=');
+        let name = parts.next().unwrap();
+        let inline_value = parts.next();
+        let spec = command.options.iter()
+            .find(|option| option.name == name)
+            .ok_or(format!("Unknown option for {}: --{}", command.name, name))?;
+        if options.iter().any(|&(ref option, _)| option == name) {
+            return Err(format!("--{} was given more than once", name));
+        }
+
+        let value = match (spec.value, inline_value) {
+            (Some(_), Some(value)) => Some(value.to_owned()),
+            (Some(_), None) => Some(args.next().ok_or(format!("--{} needs a value", name))?.clone()),
+            (None, Some(_)) => return Err(format!("--{} doesn't take a value", name)),
+            (None, None) => None,
+        };
+
+        options.push((name.to_owned(), value));
+    }
+
+    Ok(Arguments { command: command.name.to_owned(), options: options })
+}
+
+pub fn usage() -> String {
+    let mut text = "Usage: chess [COMMAND] [OPTIONS]\n".to_owned();
+    for command in COMMANDS {
+        text.push_str(&format!("\n{}: {}\n", command.name, command.summary));
+        for option in command.options {
+            let name = match option.value {
+                Some(value) => format!("--{} {}", option.name, value),
+                None => format!("--{}", option.name),
+            };
+            text.push_str(&format!("    {:<24} {}\n", name, option.help));
+        }
+    }
+
+    text
+}
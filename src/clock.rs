@@ -0,0 +1,192 @@
+use std::time::Duration;
+use std::time::Instant;
+
+use computer_player::SearchLimits;
+
+// A week, which is longer than any game needs. Larger times are surely mistakes, and far larger ones
+// would overflow when converted.
+const MAX_SECONDS: f64 = 7.0 * 24.0 * 60.0 * 60.0;
+
+// How much thinking time each player gets over the course of a game.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeControl {
+    // A fixed amount of time for the whole game.
+    SuddenDeath(Duration),
+    // Fischer increment: a fixed amount of time is added after every move.
+    Increment { base: Duration, increment: Duration },
+    // Bronstein delay: after every move, the time used is given back up to the delay.
+    Delay { base: Duration, delay: Duration },
+    // The time is given again after every so many moves, such as 40 moves in 90 minutes.
+    MovesIn { moves: u32, time: Duration },
+}
+
+impl TimeControl {
+    // Parses "300" for sudden death, "300+2" for an increment, "300d2" for a delay, or "40/5400" for
+    // moves in a time, all in seconds. These follow the PGN TimeControl tag, apart from delays,
+    // which it has no notation for.
+    pub fn parse(text: &str) -> Option<TimeControl> {
+        let seconds = |text: &str| text.parse::<f64>().ok().and_then(duration_from_seconds);
+        let split = |separator: char| {
+            let mut parts = text.splitn(2, separator);
+            (parts.next().unwrap(), parts.next())
+        };
+
+        let time_control = if let (base, Some(increment)) = split('+') {
+            TimeControl::Increment { base: seconds(base)?, increment: seconds(increment)? }
+        } else if let (base, Some(delay)) = split('d') {
+            TimeControl::Delay { base: seconds(base)?, delay: seconds(delay)? }
+        } else if let (moves, Some(time)) = split('/') {
+            match moves.parse() {
+                Ok(moves) if moves > 0 => TimeControl::MovesIn { moves: moves, time: seconds(time)? },
+                _ => return None,
+            }
+        } else {
+            TimeControl::SuddenDeath(seconds(text)?)
+        };
+
+        if time_control.base() > Duration::from_secs(0) {
+            Some(time_control)
+        } else {
+            None
+        }
+    }
+
+    pub fn format(&self) -> String {
+        match *self {
+            TimeControl::SuddenDeath(base) => format_seconds(base),
+            TimeControl::Increment { base, increment } =>
+                format!("{}+{}", format_seconds(base), format_seconds(increment)),
+            TimeControl::Delay { base, delay } => format!("{}d{}", format_seconds(base), format_seconds(delay)),
+            TimeControl::MovesIn { moves, time } => format!("{}/{}", moves, format_seconds(time)),
+        }
+    }
+
+    // The time on the clock at the start of the game.
+    fn base(&self) -> Duration {
+        match *self {
+            TimeControl::SuddenDeath(base) => base,
+            TimeControl::Increment { base, .. } => base,
+            TimeControl::Delay { base, .. } => base,
+            TimeControl::MovesIn { time, .. } => time,
+        }
+    }
+}
+
+// One player's clock. It only runs between start and stop, so the opponent's thinking time and the
+// time spent between moves aren't counted.
+pub struct Clock {
+    time_control: TimeControl,
+    time_left: Duration,
+    moves_made: u32,
+    turn_start: Option<Instant>,
+}
+
+impl Clock {
+    pub fn new(time_control: TimeControl) -> Clock {
+        Clock {
+            time_control: time_control,
+            time_left: time_control.base(),
+            moves_made: 0,
+            turn_start: None,
+        }
+    }
+
+    pub fn start(&mut self) {
+        self.turn_start = Some(Instant::now());
+    }
+
+    // Stops the clock once a move has been made, returning false if the flag fell before then.
+    pub fn stop(&mut self) -> bool {
+        let elapsed = match self.turn_start.take() {
+            Some(turn_start) => turn_start.elapsed(),
+            None => panic!("The clock was stopped without being started!"),
+        };
+
+        if elapsed > self.time_left {
+            self.time_left = Duration::from_secs(0);
+            return false;
+        }
+
+        self.time_left -= elapsed;
+        self.moves_made += 1;
+        match self.time_control {
+            TimeControl::SuddenDeath(_) => (),
+            TimeControl::Increment { increment, .. } => self.time_left += increment,
+            TimeControl::Delay { delay, .. } => self.time_left += if elapsed < delay { elapsed } else { delay },
+            TimeControl::MovesIn { moves, time } => if self.moves_made % moves == 0 {
+                self.time_left += time;
+            },
+        }
+
+        true
+    }
+
+    // What the player can afford to spend on its next move.
+    pub fn search_limits(&self) -> SearchLimits {
+        let (increment, moves_to_go) = match self.time_control {
+            TimeControl::SuddenDeath(_) => (Duration::from_secs(0), None),
+            TimeControl::Increment { increment, .. } => (increment, None),
+            // The delay is at least given back on every move that takes that long.
+            TimeControl::Delay { delay, .. } => (delay, None),
+            TimeControl::MovesIn { moves, .. } => (Duration::from_secs(0), Some(moves - self.moves_made % moves)),
+        };
+
+        SearchLimits {
+            time_left: Some(self.time_left),
+            increment: increment,
+            moves_to_go: moves_to_go,
+            .. SearchLimits::default()
+        }
+    }
+
+    // The time left as minutes and seconds, such as 4:05.3.
+    pub fn format(&self) -> String {
+        let tenths = self.time_left.as_secs() * 10 + self.time_left.subsec_nanos() as u64 / 100000000;
+        format!("{}:{:02}.{}", tenths / 600, tenths / 10 % 60, tenths % 10)
+    }
+}
+
+// Whole seconds are written without a fraction, as PGN expects.
+// Reads a time given in seconds, such as 2.5. Negative, infinite and overly long times are rejected,
+// as is NaN.
+pub fn duration_from_seconds(seconds: f64) -> Option<Duration> {
+    if seconds.is_finite() && seconds >= 0.0 && seconds <= MAX_SECONDS {
+        Some(Duration::from_millis((seconds * 1000.0) as u64))
+    } else {
+        None
+    }
+}
+
+fn format_seconds(duration: Duration) -> String {
+    if duration.subsec_nanos() == 0 {
+        duration.as_secs().to_string()
+    } else {
+        format!("{}", duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1e9)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_time_controls() {
+        assert_eq!(TimeControl::parse("300"), Some(TimeControl::SuddenDeath(Duration::from_secs(300))));
+        assert_eq!(TimeControl::parse("2.5+0.5"), Some(TimeControl::Increment {
+            base: Duration::from_millis(2500), increment: Duration::from_millis(500),
+        }));
+        assert_eq!(TimeControl::parse("300d2"), Some(TimeControl::Delay {
+            base: Duration::from_secs(300), delay: Duration::from_secs(2),
+        }));
+        assert_eq!(TimeControl::parse("40/5400"), Some(TimeControl::MovesIn {
+            moves: 40, time: Duration::from_secs(5400),
+        }));
+    }
+
+    #[test]
+    fn reject_bad_times() {
+        for text in ["", "0", "-5", "inf", "NaN", "300+inf", "300dNaN", "1e30", "300+1e30", "0/60", "x/60"].iter() {
+            assert_eq!(TimeControl::parse(text), None, "{}", text);
+        }
+    }
+}
@@ -1,209 +1,739 @@
+use std::cell::Cell;
 use std::cmp::Ordering;
 use std::i16;
+use std::i32;
+use std::u8;
 use std::cmp;
+use std::sync::Arc;
+use std::sync::atomic;
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+use std::time::Instant;
 use rand;
-use rand::Rng;
 
 use piece_type::PieceType;
 use piece_type::PieceType::*;
+use piece_type::ALL_PIECE_TYPES;
 use piece_move::Move;
 use game_state::GameState;
 use game_state::PlayerState;
 use game_state::Color;
-use game_state::EndState;
+use player::Player;
+use pgn;
+use random::Random;
+use transposition_table;
+use transposition_table::Bound;
+use transposition_table::Entry;
+use transposition_table::TranspositionTable;
 
 const MAX_SCORE: i16 = 10000;
+// Beyond any score, and still safe to negate.
+const INFINITY: i16 = MAX_SCORE + 1;
+// Scores beyond this are checkmates, adjusted by how many plies away they are.
+const MIN_MATE_SCORE: i16 = MAX_SCORE - u8::MAX as i16;
+// Evaluations are kept below the checkmate scores, however large the weights are.
+pub const MAX_EVALUATION: i32 = MIN_MATE_SCORE as i32 - 1;
+
+// Used when a search is given no limits at all.
+const DEFAULT_DEPTH: u8 = 3;
+// Caps the depth when a search is only limited by time or by being stopped.
+pub const MAX_DEPTH: u8 = 64;
+// Assumed number of moves left until the next time control when it isn't known.
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+// The pawns of leeway given by delta pruning for positional gains on top of the material won.
+const DELTA_MARGIN: i32 = 2;
+
+pub const EVALUATOR_NAMES: [&'static str; 4] = ["piece_score", "max_moves", "max_spaces", "spaces_moves"];
+
+// A function that scores positions from White's perspective.
+pub struct Evaluator {
+    eval_function: Box<dyn Fn(&GameState) -> i16>,
+    // What each piece from the pawn up to the queen is worth in the evaluation's units. The quiescence
+    // search tries capturing the most valuable pieces first, and when a pawn is worth something it
+    // skips captures that can't make a difference. Without them, captures are ordered by the default
+    // piece values and every one is searched.
+    piece_values: Option<[i32; 5]>,
+    // Whether captures are searched past the depth limit until the position is quiet. Without it, the
+    // evaluation at the depth limit is used as it is.
+    quiescence: bool,
+}
+
+impl Evaluator {
+    pub fn new(eval_function: Box<dyn Fn(&GameState) -> i16>, piece_values: Option<[i32; 5]>) -> Evaluator {
+        Evaluator { eval_function: eval_function, piece_values: piece_values, quiescence: true }
+    }
+
+    pub fn set_quiescence(&mut self, quiescence: bool) {
+        self.quiescence = quiescence;
+    }
+
+    pub fn evaluate(&self, game_state: &GameState) -> i16 {
+        (self.eval_function)(game_state)
+    }
+
+    // A score in hundredths of a pawn, as UCI reports them. Without a pawn value the score is left in
+    // the evaluation's own units.
+    pub fn centipawns(&self, score: i16) -> i32 {
+        match self.piece_values.map(|piece_values| piece_values[Pawn.index()]) {
+            Some(pawn_value) if pawn_value > 0 => (score as i64 * 100 / pawn_value as i64) as i32,
+            _ => score as i32,
+        }
+    }
+
+    // Kings are never captured, so they are only ever attackers, and are tried last.
+    fn piece_value(&self, piece_type: PieceType) -> i32 {
+        match piece_type {
+            King => i32::MAX,
+            _ => self.piece_values.map_or(DEFAULT_PIECE_VALUES[piece_type.index()] as i32,
+                |piece_values| piece_values[piece_type.index()]),
+        }
+    }
+}
+
+// How much each scorer counts for in multi_eval, in the order of SCORER_NAMES.
+pub type Weights = [i16; 3];
+
+pub const SCORER_NAMES: [&'static str; 3] = ["piece", "spaces", "moves"];
+
+// What the piece scorer counts each piece as, from the pawn up to the queen. Kings are left out,
+// since each side always has one.
+pub type PieceValues = [i16; 5];
+
+pub const DEFAULT_PIECE_VALUES: PieceValues = [1, 3, 3, 5, 9];
+
+// The weights behind each of the built in AIs.
+pub fn evaluator_weights(name: &str) -> Option<Weights> {
+    match name {
+        "piece_score" => Some([15, 0, 0]),
+        "max_moves" => Some([15, 0, 1]),
+        "max_spaces" => Some([15, 3, 0]),
+        "spaces_moves" => Some([70, 7, 1]),
+        _ => None,
+    }
+}
+
+// Adds up the scorers with the given weights, so a pawn is worth the piece weight times its value.
+pub fn weighted_evaluator(weights: Weights, piece_values: PieceValues) -> Evaluator {
+    let mut weighted_values = [0; 5];
+    for (weighted_value, &value) in weighted_values.iter_mut().zip(piece_values.iter()) {
+        *weighted_value = weights[0] as i32 * value as i32;
+    }
 
-pub fn piece_score_comp(initial_game_state: &GameState, moves: &Vec<Move>, max_depth: u8) -> Move {
-    computer_player(initial_game_state, &moves, "PIECE SCORE".to_owned(), max_depth,
-        Box::new(|game_state| multi_eval(game_state,
-            &[(15, &piece_scorer())])))
+    Evaluator::new(
+        Box::new(move |game_state| multi_eval(game_state, &[
+            (weights[0], &piece_scorer(piece_values)),
+            (weights[1], &spaces_scorer()),
+            (weights[2], &moves_scorer())])),
+        Some(weighted_values))
 }
 
-pub fn max_moves_comp(initial_game_state: &GameState, moves: &Vec<Move>, max_depth: u8) -> Move {
-    computer_player(initial_game_state, &moves, "MAX MOVES".to_owned(), max_depth,
-        Box::new(|game_state| multi_eval(game_state,
-            &[(15, &piece_scorer()), (1, &moves_scorer())])))
+// The unweighted parts of the evaluation from White's perspective: the difference in the number of
+// each piece from the pawn up to the queen, then the space and move scores. Any weights and piece
+// values give the same evaluation as weighted_evaluator when applied to these, so tuning can try
+// them without generating the position's moves again.
+pub fn evaluation_terms(game_state: &GameState) -> [i16; 7] {
+    let white_moves = game_state.get_player_moves_without_check(Color::White);
+    let black_moves = game_state.get_player_moves_without_check(Color::Black);
+    let mut terms = [0; 7];
+    for (term, piece_type) in terms.iter_mut().zip(ALL_PIECE_TYPES.iter()) {
+        *term = game_state.count_pieces(*piece_type, Color::White) as i16
+            - game_state.count_pieces(*piece_type, Color::Black) as i16;
+    }
+
+    terms[5] = spaces_scorer()(game_state, &white_moves, &black_moves) as i16;
+    terms[6] = moves_scorer()(game_state, &white_moves, &black_moves) as i16;
+    terms
 }
 
-pub fn max_spaces_comp(initial_game_state: &GameState, moves: &Vec<Move>, max_depth: u8) -> Move {
-    computer_player(initial_game_state, &moves, "MAX SPACES".to_owned(), max_depth,
-        Box::new(|game_state| multi_eval(game_state,
-            &[(15, &piece_scorer()), (3, &spaces_scorer())])))
+// How many of each piece from the pawn up to the queen each side starts with.
+const STARTING_PIECE_COUNTS: [i32; 5] = [8, 2, 2, 2, 1];
+// The most squares a side can control, and the most moves it can have: a king's eight, and a queen's
+// twenty seven for each of its other fifteen pieces.
+const MAX_SPACE_SCORE: i32 = 64;
+const MAX_MOVE_SCORE: i32 = 8 + 15 * 27;
+
+// The largest evaluation, for either side, that the terms of evaluation_terms can add up to with these
+// weights, which mustn't be negative. No side has more pieces than it starts with, though each of its
+// pawns can become whichever piece is worth the most.
+pub fn max_evaluation(term_weights: &[i32; 7]) -> i32 {
+    let most_valuable = term_weights[..5].iter().cloned().max().unwrap();
+    let pieces = term_weights[1..5].iter().zip(STARTING_PIECE_COUNTS[1..].iter())
+        .map(|(&weight, &count)| weight * count)
+        .sum::<i32>();
+    pieces + STARTING_PIECE_COUNTS[0] * most_valuable
+        + term_weights[5] * MAX_SPACE_SCORE + term_weights[6] * MAX_MOVE_SCORE
 }
 
-pub fn spaces_moves_comp(initial_game_state: &GameState, moves: &Vec<Move>, max_depth: u8) -> Move {
-    computer_player(initial_game_state, &moves, "SPACES MOVES".to_owned(), max_depth,
-        Box::new(|game_state| multi_eval(game_state,
-            &[(70, &piece_scorer()), (7, &spaces_scorer()), (1, &moves_scorer())])))
+// What a search may spend on a move: a depth, a fixed time, or a share of the time left on the
+// player's clock. The search stops at whichever limit is reached first.
+#[derive(Clone, Default, Debug)]
+pub struct SearchLimits {
+    pub depth: Option<u8>,
+    pub movetime: Option<Duration>,
+    pub time_left: Option<Duration>,
+    pub increment: Duration,
+    pub moves_to_go: Option<u32>,
+    // Searches until stopped, ignoring the time limits.
+    pub infinite: bool,
 }
 
-fn computer_player(
-        initial_game_state: &GameState,
+impl SearchLimits {
+    pub fn depth(depth: u8) -> SearchLimits {
+        SearchLimits { depth: Some(depth), .. SearchLimits::default() }
+    }
+
+    pub fn max_depth(&self) -> u8 {
+        match self.depth {
+            Some(depth) => cmp::max(1, cmp::min(depth, MAX_DEPTH)),
+            None if self.infinite || self.time_budget().is_some() => MAX_DEPTH,
+            None => DEFAULT_DEPTH,
+        }
+    }
+
+    // How long the move may take, if it is limited by time. With both a time per move and a clock,
+    // whichever runs out first is the limit.
+    pub fn time_budget(&self) -> Option<Duration> {
+        if self.infinite {
+            return None;
+        }
+
+        let clock_budget = self.time_left.map(|time_left| {
+            let moves_to_go = cmp::max(1, self.moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO));
+            // Never spend more than half of what is left, however large the increment is.
+            cmp::min(time_left / moves_to_go + self.increment, time_left / 2)
+        });
+        match (self.movetime, clock_budget) {
+            (Some(movetime), Some(clock_budget)) => Some(cmp::min(movetime, clock_budget)),
+            (movetime, clock_budget) => movetime.or(clock_budget),
+        }
+    }
+}
+
+// The results of a completed iteration of iterative deepening.
+pub struct SearchIteration {
+    pub depth: u8,
+    // Scores are from the perspective of the player to move, sorted from best to worst.
+    pub move_scores: Vec<(Move, i16)>,
+    // The best move followed by the replies that each player is expected to make.
+    pub principal_variation: Vec<Move>,
+    pub elapsed: Duration,
+}
+
+// Searches one ply deeper at a time until the limits are reached or stop is set, returning the
+// deepest iteration to complete. An iteration that runs out of time partway through is abandoned,
+// but the first one always completes so that there is a move to play. on_iteration is called as
+// each iteration completes.
+pub fn iterative_deepening(
+        game_state: &GameState,
         moves: &Vec<Move>,
-        name: String,
-        max_depth: u8,
-        eval_function: Box<Fn(&GameState) -> i16>)
-        -> Move {
-
-    if moves.len() == 0 {
-        panic!("No possible moves passed to computer player!");
-    }
-
-    let move_scores = determine_best_moves(
-        None,
-        &initial_game_state,
-        moves,
-        &eval_function,
-        i16::MIN,
-        i16::MAX,
-        max_depth,
-        max_depth).0;
-    if let [(_, best_score), ..] = move_scores.as_slice() {
-        let best_moves = move_scores.clone().into_iter()
-            .take_while(|&(_, score)| score == best_score)
-            .map(|(m, _)| m)
+        evaluator: &Evaluator,
+        limits: &SearchLimits,
+        table: &mut TranspositionTable,
+        stop: Arc<AtomicBool>,
+        on_iteration: &mut dyn FnMut(&SearchIteration))
+        -> SearchIteration {
+
+    let start = Instant::now();
+    let time_budget = limits.time_budget();
+    let mut game_state = game_state.clone();
+    // The first iteration can't be stopped, so that there is always a move to play.
+    let (move_scores, principal_variation) = search_root(
+        &mut game_state, moves, evaluator, 1, table, &SearchControl::new(Arc::new(AtomicBool::new(false)), None));
+    let mut result = SearchIteration {
+        depth: 1,
+        move_scores: move_scores,
+        principal_variation: principal_variation,
+        elapsed: start.elapsed(),
+    };
+    on_iteration(&result);
+
+    for depth in 2..=limits.max_depth() {
+        // The next depth would take several times as long as the last one, so don't bother
+        // starting it when more than half of the budget has been used.
+        let out_of_time = time_budget.map_or(false, |budget| result.elapsed * 2 >= budget);
+        if stop.load(atomic::Ordering::SeqCst) || out_of_time {
+            break;
+        }
+
+        // Each iteration searches the moves in the order that the previous one ranked them.
+        let ordered_moves = result.move_scores.iter()
+            .map(|&(ref piece_move, _)| piece_move.clone())
             .collect::<Vec<_>>();
-        println!("Total moves possible: {}", move_scores.len());
-        println!("Best moves according to the {} AI ({:?}):\n{}",
-            name,
-            initial_game_state.current_player,
-            move_scores.clone().into_iter()
-                 .take(5)
-                 .fold("".to_owned(), |mut text, (piece_move, score)| {
-                       text.push_str(format!("{}: {}, ", piece_move.simple_format(), score).as_str());
-                       text
-                 }));
+        let control = SearchControl::new(stop.clone(), time_budget.map(|budget| start + budget));
+        let (move_scores, principal_variation) = search_root(
+            &mut game_state, &ordered_moves, evaluator, depth, table, &control);
+        if control.is_aborted() {
+            break;
+        }
+
+        result = SearchIteration {
+            depth: depth,
+            move_scores: move_scores,
+            principal_variation: principal_variation,
+            elapsed: start.elapsed(),
+        };
+        on_iteration(&result);
+    }
+
+    result
+}
+
+// Lets a search in progress be cut short, either from another thread or once its time is up.
+struct SearchControl {
+    stop: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+    // Once aborted the search stays aborted, so that every level of it unwinds without storing
+    // incomplete results.
+    is_aborted: Cell<bool>,
+}
+
+impl SearchControl {
+    fn new(stop: Arc<AtomicBool>, deadline: Option<Instant>) -> SearchControl {
+        SearchControl { stop: stop, deadline: deadline, is_aborted: Cell::new(false) }
+    }
+
+    fn is_aborted(&self) -> bool {
+        if !self.is_aborted.get() && (self.stop.load(atomic::Ordering::Relaxed)
+                || self.deadline.map_or(false, |deadline| Instant::now() >= deadline)) {
+            self.is_aborted.set(true);
+        }
+
+        self.is_aborted.get()
+    }
+}
+
+// An AI that searches with one of the evaluation functions. It keeps its transposition table from
+// one move to the next, and resigns once it sees that it's being checkmated.
+pub struct ComputerPlayer {
+    name: String,
+    evaluator: Evaluator,
+    table: TranspositionTable,
+    // The score of the last move chosen, from this player's perspective.
+    last_score: Option<i16>,
+    // Used in place of the depth and time per move that the game is played at.
+    depth: Option<u8>,
+    movetime: Option<Duration>,
+    // Picks between equally good moves. Without randomness, the first is always played.
+    rng: Random,
+    randomness: bool,
+    // Whether to print the search results for each move.
+    verbose: bool,
+}
+
+impl ComputerPlayer {
+    pub fn new(name: &str, evaluator: Evaluator) -> ComputerPlayer {
+        ComputerPlayer {
+            name: name.to_owned(),
+            evaluator: evaluator,
+            table: TranspositionTable::new(transposition_table::DEFAULT_SIZE_MB),
+            last_score: None,
+            depth: None,
+            movetime: None,
+            rng: Random::new(rand::random()),
+            randomness: true,
+            verbose: true,
+        }
+    }
+
+    // The clock still applies in timed games, since time_budget stops at whichever limit comes first.
+    pub fn set_limits(&mut self, depth: Option<u8>, movetime: Option<Duration>) {
+        self.depth = depth;
+        self.movetime = movetime;
+    }
 
-        let mut rng = rand::thread_rng();
-        return best_moves[rng.gen_range(0, best_moves.len()) as usize].clone();
+    pub fn set_randomness(&mut self, randomness: bool) {
+        self.randomness = randomness;
     }
 
-    panic!(format!("No moves returned by player {:?}", initial_game_state.current_player));
+    pub fn set_hash_size(&mut self, size_mb: usize) {
+        self.table = TranspositionTable::new(size_mb);
+    }
+
+    pub fn set_verbose(&mut self, verbose: bool) {
+        self.verbose = verbose;
+    }
+}
+
+impl Player for ComputerPlayer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn choose_move(&mut self, initial_game_state: &GameState, moves: &[Move], limits: &SearchLimits) -> Move {
+        if moves.len() == 0 {
+            panic!("No possible moves passed to computer player!");
+        }
+
+        let limits = SearchLimits {
+            depth: self.depth.or(limits.depth),
+            movetime: self.movetime.or(limits.movetime),
+            .. limits.clone()
+        };
+        self.table.reset_stats();
+        let iteration = iterative_deepening(
+            &initial_game_state, &moves.to_vec(), &self.evaluator, &limits, &mut self.table,
+            Arc::new(AtomicBool::new(false)), &mut |_| ());
+        let move_scores = iteration.move_scores;
+        if let &[(_, best_score), ..] = move_scores.as_slice() {
+            let best_moves = move_scores.clone().into_iter()
+                .take_while(|&(_, score)| score == best_score)
+                .map(|(m, _)| m)
+                .collect::<Vec<_>>();
+            if self.verbose {
+                println!("Searched to depth {} in {:.2}s", iteration.depth,
+                    iteration.elapsed.as_secs() as f64 + iteration.elapsed.subsec_nanos() as f64 / 1e9);
+                println!("{}", self.table.format_stats());
+                println!("Total moves possible: {}", move_scores.len());
+                println!("Best moves according to the {} AI ({:?}):\n{}",
+                    self.name,
+                    initial_game_state.current_player,
+                    move_scores.clone().into_iter()
+                         .take(5)
+                         .fold("".to_owned(), |mut text, (piece_move, score)| {
+                               text.push_str(format!("{}: {}, ", piece_move.simple_format(), score).as_str());
+                               text
+                         }));
+                println!("Expected line: {}",
+                    pgn::format_line(initial_game_state, &iteration.principal_variation));
+            }
+
+            self.last_score = Some(best_score);
+            let choice = if self.randomness { self.rng.below(best_moves.len()) } else { 0 };
+            return best_moves[choice].clone();
+        }
+
+        panic!(format!("No moves returned by player {:?}", initial_game_state.current_player));
+    }
+
+    fn new_game(&mut self) {
+        self.table.clear();
+        self.last_score = None;
+    }
+
+    // With a fixed seed and a depth limit, the player always makes the same moves in the same games.
+    fn set_seed(&mut self, seed: u64) {
+        self.rng = Random::new(seed);
+    }
+
+    fn resigns(&mut self, _game_state: &GameState) -> bool {
+        self.last_score.and_then(moves_to_mate).map_or(false, |moves| moves < 0)
+    }
+
+    // Only takes a draw when the last search thought the position was worse than that.
+    fn accepts_draw(&mut self, _game_state: &GameState) -> bool {
+        self.last_score.map_or(false, |score| score < 0)
+    }
 }
 
-// Returns a list of pairs of moves with scores, sorted from best to worst.
-fn determine_best_moves(
-        previous_game_state: Option<&GameState>,
-        initial_game_state: &GameState,
+// Scores every move from the perspective of the player to move, returning them sorted from best to
+// worst along with the principal variation. Only the best score is guaranteed to be exact. Worse
+// moves are only searched far enough to show that they are worse, but moves that tie with the
+// best are always exact so that any of them can be played.
+fn search_root(
+        game_state: &mut GameState,
         moves: &Vec<Move>,
-        eval_function: &Box<Fn(&GameState) -> i16>,
+        evaluator: &Evaluator,
+        depth: u8,
+        table: &mut TranspositionTable,
+        control: &SearchControl)
+        -> (Vec<(Move, i16)>, Vec<Move>) {
+
+    let mut move_scores: Vec<(Move, i16)> = vec![];
+    let mut principal_variation = vec![];
+    let mut best_score = -INFINITY;
+    for piece_move in moves {
+        let undo = game_state.make_move(piece_move);
+        // Searching just below the best score means that a move matching it comes back exact.
+        let alpha = if best_score == -INFINITY { -INFINITY } else { best_score - 1 };
+        let (score, next_variation) = negamax(game_state, evaluator, -INFINITY, -alpha, depth - 1, 1, table, control);
+        let score = -score;
+        game_state.unmake_move(piece_move, &undo);
+        if control.is_aborted() {
+            return (vec![], vec![]);
+        }
+
+        if score > best_score {
+            best_score = score;
+            principal_variation = vec![piece_move.clone()];
+            principal_variation.extend(next_variation);
+        }
+
+        move_scores.push((piece_move.clone(), score));
+    }
+
+    // Stable, so that moves keep their order from the previous iteration when tied.
+    move_scores.sort_by(|&(_, score0), &(_, score1)| score1.cmp(&score0));
+    (move_scores, principal_variation)
+}
+
+// Returns the score of the position from the perspective of the player to move, and the principal
+// variation leading to it. Fails soft, so when the score is outside of alpha and beta it is a bound
+// on the true score, which is still more informative than alpha or beta themselves.
+fn negamax(
+        game_state: &mut GameState,
+        evaluator: &Evaluator,
         mut alpha: i16,
-        mut beta: i16,
-        max_ply: u8,
-        ply: u8)
-        -> (Vec<(Move, i16)>, i16) {
-
-    if ply == 0 {
-        panic!("Zero ply specified!");
-    }
-
-    match initial_game_state.get_end_state(&moves) {
-        EndState::NotEnded => (),
-        // TODO: Unify checkmate/stalemate handling.
-        EndState::Win(_) => {
-            // Despite being labeled as a Win, it is possible this was actually a stalemate.
-            match previous_game_state.map(|gs| gs.get_player_moves()) {
-                Some(PlayerState::Stalemate) => return (vec![], 0),
-                _ => return (vec![],
-                    // In case of a checkmate, favor earlier checkmates by making later ones slightly less
-                    // valuable.
-                    (MAX_SCORE - max_ply as i16 + ply as i16) *
-                        if initial_game_state.current_player == Color::White { -1 } else { 1 }),
+        beta: i16,
+        depth: u8,
+        plies_from_root: u8,
+        table: &mut TranspositionTable,
+        control: &SearchControl)
+        -> (i16, Vec<Move>) {
+
+    let moves = match game_state.get_player_moves_in_place() {
+        PlayerState::CanMove(moves) => moves,
+        // Later checkmates are slightly less bad, so that the winning player goes for the
+        // quickest one.
+        PlayerState::Checkmate => return (-(MAX_SCORE - plies_from_root as i16), vec![]),
+        PlayerState::Stalemate | PlayerState::Draw(_) => return (0, vec![]),
+    };
+
+    if depth == 0 {
+        return (quiescence(game_state, evaluator, alpha, beta, control), vec![]);
+    }
+
+    let original_alpha = alpha;
+    let hash = game_state.hash();
+    let mut table_move = None;
+    if let Some(entry) = table.probe(hash) {
+        if entry.depth >= depth {
+            let score = score_from_table(entry.score, plies_from_root);
+            let is_cutoff = match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha,
+            };
+            if is_cutoff {
+                table.record_cutoff();
+                // The table doesn't keep whole variations, so this is where the line ends.
+                return (score, vec![]);
             }
-        },
-        EndState::Stalemate => return (
-            moves.iter().zip([0].iter().cycle()).map(|(s, c)| (s.clone(), c.clone())).collect::<Vec<_>>(),
-            0),
-    }
-
-    let current_player = initial_game_state.current_player;
-
-    let ordered_moves = if ply > 1 {
-        // Improve the ordering of moves so that alpha beta pruning is more efficient.
-        determine_best_moves(
-                None,
-                &initial_game_state,
-                &moves,
-                &eval_function,
-                -MAX_SCORE,
-                MAX_SCORE,
-                max_ply,
-                1)
-            .0
-            .into_iter()
-            .map(|(m, _)| m)
+        }
+
+        table_move = entry.best_move;
+    }
+
+    let mut best_score = -INFINITY;
+    let mut principal_variation = vec![];
+    for piece_move in order_moves(game_state, moves, evaluator, table_move, depth) {
+        let undo = game_state.make_move(&piece_move);
+        let (score, next_variation) = negamax(
+            game_state, evaluator, -beta, -alpha, depth - 1, plies_from_root + 1, table, control);
+        let score = -score;
+        game_state.unmake_move(&piece_move, &undo);
+        if control.is_aborted() {
+            return (0, vec![]);
+        }
+
+        if score > best_score {
+            best_score = score;
+            principal_variation = vec![piece_move];
+            principal_variation.extend(next_variation);
+        }
+
+        alpha = cmp::max(alpha, score);
+        if alpha >= beta {
+            break;
+        }
+    }
+
+    let bound = if best_score <= original_alpha {
+        Bound::Upper
+    } else if best_score >= beta {
+        Bound::Lower
+    } else {
+        Bound::Exact
+    };
+    table.store(Entry {
+        hash: hash,
+        depth: depth,
+        bound: bound,
+        score: score_to_table(best_score, plies_from_root),
+        best_move: principal_variation.first().cloned(),
+    });
+
+    (best_score, principal_variation)
+}
+
+// Puts the moves most likely to cause a cutoff first: the best move from a previous search of the
+// position, then the rest by the evaluation of the position they lead to. The evaluations aren't
+// worth their cost right before the horizon, where cutoffs save little.
+fn order_moves(
+        game_state: &mut GameState,
+        moves: Vec<Move>,
+        evaluator: &Evaluator,
+        table_move: Option<Move>,
+        depth: u8)
+        -> Vec<Move> {
+
+    let sign = if game_state.current_player == Color::White { 1 } else { -1 };
+    let mut move_scores = if depth > 1 {
+        moves.into_iter()
+            .map(|piece_move| {
+                let undo = game_state.make_move(&piece_move);
+                let score = sign * evaluator.evaluate(game_state);
+                game_state.unmake_move(&piece_move, &undo);
+                (piece_move, score)
+            })
             .collect::<Vec<_>>()
     } else {
-        moves.clone()
+        moves.into_iter().map(|piece_move| (piece_move, 0)).collect::<Vec<_>>()
     };
 
-    let mut move_scores: Vec<(Move, i16)> = vec![];
-    for piece_move in ordered_moves {
-        let mut game_state = initial_game_state.clone();
-        game_state.move_piece(&piece_move);
-
-        let score = if ply > 1 {
-            // Determine the other player's best move
-            let next_moves = game_state.get_player_moves_without_check(game_state.current_player);
-            match determine_best_moves(
-                    Some(initial_game_state),
-                    &game_state,
-                    &next_moves,
-                    eval_function,
-                    alpha,
-                    beta,
-                    max_ply,
-                    ply - 1) {
-                (_, score) => score,
+    if let Some(table_move) = table_move {
+        for &mut (ref piece_move, ref mut score) in move_scores.iter_mut() {
+            if *piece_move == table_move {
+                *score = i16::MAX;
             }
+        }
+    }
+
+    move_scores.sort_by(|&(_, score0), &(_, score1)| score1.cmp(&score0));
+    move_scores.into_iter().map(|(piece_move, _)| piece_move).collect()
+}
+
+// Searches captures and promotions until the position is quiet, so that pieces left hanging at the
+// end of the main search are accounted for. The player to move can also "stand pat" on the static
+// evaluation rather than make any of them. Scores are from the perspective of the player to move.
+fn quiescence(
+        game_state: &mut GameState,
+        evaluator: &Evaluator,
+        mut alpha: i16,
+        beta: i16,
+        control: &SearchControl)
+        -> i16 {
+
+    let current_player = game_state.current_player;
+    let sign = if current_player == Color::White { 1 } else { -1 };
+    let stand_pat = sign * evaluator.evaluate(game_state);
+    if stand_pat >= beta || !evaluator.quiescence {
+        return stand_pat;
+    }
+
+    alpha = cmp::max(alpha, stand_pat);
+
+    let mut noisy_moves = game_state.get_player_moves_without_check(current_player).into_iter()
+        .filter_map(|piece_move| {
+            let victim = game_state.get_piece(&piece_move.destination).map(|piece| piece.piece_type)
+                .or(piece_move.en_passant_target.as_ref().map(|_| Pawn));
+            if victim.is_some() || piece_move.promotion_piece_type.is_some() {
+                Some((victim, piece_move))
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // Trying the most valuable victims first, taken by the least valuable attackers, makes cutoffs
+    // more likely.
+    noisy_moves.sort_by_key(|&(victim, ref piece_move)| {
+        let attacker = game_state.get_piece(&piece_move.source).unwrap().piece_type;
+        (-victim.map_or(0, |victim| evaluator.piece_value(victim)), evaluator.piece_value(attacker))
+    });
+
+    let delta_pawn_value = evaluator.piece_values
+        .map(|piece_values| piece_values[Pawn.index()])
+        .filter(|&pawn_value| pawn_value > 0);
+    let mut best_score = stand_pat;
+    for (victim, piece_move) in noisy_moves {
+        // Delta pruning: skip moves that couldn't bring the score up to alpha even if they won
+        // their material with a couple of pawns to spare.
+        if let Some(pawn_value) = delta_pawn_value {
+            let promotion_gain = piece_move.promotion_piece_type
+                .map_or(0, |piece_type| evaluator.piece_value(piece_type) - pawn_value);
+            // Weighted piece values can be large enough for their sum to overflow an i32.
+            let gain = victim.map_or(0, |victim| evaluator.piece_value(victim)) as i64 + promotion_gain as i64;
+            if stand_pat as i64 + gain + DELTA_MARGIN as i64 * pawn_value as i64 <= alpha as i64 {
+                continue;
+            }
+        }
+
+        // Captures are generated without regard to check, so the illegal ones are skipped here.
+        let undo = game_state.make_move(&piece_move);
+        let score = if game_state.is_in_check(current_player) {
+            None
         } else {
-            // Use the base, non-recursive heuristic if we are only looking ahead one move.
-            eval_function(&game_state)
+            Some(-quiescence(game_state, evaluator, -beta, -alpha, control))
         };
+        game_state.unmake_move(&piece_move, &undo);
+        if control.is_aborted() {
+            return 0;
+        }
 
-        if current_player == Color::White {
+        if let Some(score) = score {
+            best_score = cmp::max(best_score, score);
             alpha = cmp::max(alpha, score);
-        } else {
-            beta = cmp::min(beta, score);
+            if alpha >= beta {
+                break;
+            }
         }
+    }
 
-        move_scores.push((piece_move.clone(), score));
-        if beta <= alpha {
-            break;
-        }
+    best_score
+}
+
+// The number of moves until checkmate for a checkmate score, negative when the player to move is the
+// one being checkmated.
+pub fn moves_to_mate(score: i16) -> Option<i16> {
+    if score >= MIN_MATE_SCORE {
+        Some((MAX_SCORE - score + 1) / 2)
+    } else if score <= -MIN_MATE_SCORE {
+        Some(-(MAX_SCORE + score) / 2)
+    } else {
+        None
     }
+}
 
-    move_scores.sort_by(|&(_, score0), &(_, score1)|
-        if current_player == Color::White { score1.cmp(&score0) } else { score0.cmp(&score1) });
+// Checkmate scores are relative to the root of the search, but the table can be used by searches
+// from other roots, so they are stored relative to the position instead.
+fn score_to_table(score: i16, plies_from_root: u8) -> i16 {
+    if score >= MIN_MATE_SCORE {
+        score + plies_from_root as i16
+    } else if score <= -MIN_MATE_SCORE {
+        score - plies_from_root as i16
+    } else {
+        score
+    }
+}
 
-    let best_score = move_scores[0].1.clone();
-    (move_scores, best_score)
+fn score_from_table(score: i16, plies_from_root: u8) -> i16 {
+    if score >= MIN_MATE_SCORE {
+        score - plies_from_root as i16
+    } else if score <= -MIN_MATE_SCORE {
+        score + plies_from_root as i16
+    } else {
+        score
+    }
 }
 
 fn multi_eval(
         game_state: &GameState,
-        scorers: &[(i16, &Box<Fn(&GameState, &[Move], &[Move]) -> i16>)]) -> i16 {
+        scorers: &[(i16, &Box<dyn Fn(&GameState, &[Move], &[Move]) -> i32>)]) -> i16 {
 
-    let mut score = 0;
+    let mut score: i32 = 0;
 
     let white_moves = game_state.get_player_moves_without_check(Color::White);
     let black_moves = game_state.get_player_moves_without_check(Color::Black);
 
     for &(weight, scorer) in scorers {
         if weight != 0 {
-            score += weight * scorer(&game_state, &white_moves, &black_moves);
+            let weighted = (weight as i32).saturating_mul(scorer(&game_state, &white_moves, &black_moves));
+            score = score.saturating_add(weighted);
         }
     }
 
-    score
+    clamp_evaluation(score)
 }
 
-fn moves_scorer() -> Box<Fn(&GameState, &[Move], &[Move]) -> i16> {
-    Box::new(|_, white_moves, black_moves| white_moves.len() as i16 - black_moves.len() as i16)
+fn clamp_evaluation(score: i32) -> i16 {
+    cmp::max(-MAX_EVALUATION, cmp::min(score, MAX_EVALUATION)) as i16
 }
 
-fn spaces_scorer() -> Box<Fn(&GameState, &[Move], &[Move]) -> i16> {
+fn moves_scorer() -> Box<dyn Fn(&GameState, &[Move], &[Move]) -> i32> {
+    Box::new(|_, white_moves, black_moves| white_moves.len() as i32 - black_moves.len() as i32)
+}
+
+fn spaces_scorer() -> Box<dyn Fn(&GameState, &[Move], &[Move]) -> i32> {
     Box::new(|_, white_moves, black_moves| {
         let mut ownership_grid = [[0; 8]; 8];
 
@@ -232,22 +762,137 @@ fn spaces_scorer() -> Box<Fn(&GameState, &[Move], &[Move]) -> i16> {
     })
 }
 
-fn piece_scorer() -> Box<Fn(&GameState, &[Move], &[Move]) -> i16> {
-    Box::new(|game_state, _, _| {
-        game_state.get_all_pieces().iter().map(|piece| {
-            let sign = if piece.color == Color::White { 1 } else { -1 };
-            sign * piece_value(&piece.piece_type) as i16
+fn piece_scorer(piece_values: PieceValues) -> Box<dyn Fn(&GameState, &[Move], &[Move]) -> i32> {
+    Box::new(move |game_state, _, _| {
+        ALL_PIECE_TYPES.iter().zip(piece_values.iter()).map(|(piece_type, &value)| {
+            let count = game_state.count_pieces(*piece_type, Color::White) as i32
+                - game_state.count_pieces(*piece_type, Color::Black) as i32;
+            count * value as i32
         }).fold(0, |x, y| x + y)
     })
 }
 
-fn piece_value(piece_type: &PieceType) -> i8 {
-    match *piece_type {
-        Pawn => 1,
-        Knight => 3,
-        Bishop => 3,
-        Rook => 5,
-        Queen => 9,
-        King => 127,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use game_state::STARTING_FEN;
+
+    fn search_to_depth(fen: &str, depth: u8) -> SearchIteration {
+        let game_state = GameState::from_fen(fen).unwrap();
+        let moves = game_state.get_legal_moves();
+        let mut table = TranspositionTable::new(1);
+        let evaluator = weighted_evaluator(evaluator_weights("piece_score").unwrap(), DEFAULT_PIECE_VALUES);
+        iterative_deepening(&game_state, &moves, &evaluator, &SearchLimits::depth(depth),
+            &mut table, Arc::new(AtomicBool::new(false)), &mut |_| ())
+    }
+
+    // Mates must be found both when the mating move is the last ply searched and when the opponent
+    // gets one more ply to reply.
+    fn assert_mate_in(fen: &str, moves: u8) {
+        for &depth in [2 * moves - 1, 2 * moves].iter() {
+            let iteration = search_to_depth(fen, depth);
+            assert_eq!(moves_to_mate(iteration.move_scores[0].1), Some(moves as i16), "depth {}", depth);
+
+            let mut game_state = GameState::from_fen(fen).unwrap();
+            for player_move in &iteration.principal_variation {
+                game_state.move_piece(player_move);
+            }
+            assert_eq!(iteration.principal_variation.len(), 2 * moves as usize - 1, "depth {}", depth);
+            match game_state.get_player_moves() {
+                PlayerState::Checkmate => (),
+                _ => panic!("The principal variation doesn't end in checkmate at depth {}", depth),
+            }
+        }
+    }
+
+    // Depths outside the range that can be searched are clamped to it, rather than searching nothing.
+    #[test]
+    fn out_of_range_depths() {
+        assert_eq!(SearchLimits::depth(0).max_depth(), 1);
+        assert_eq!(SearchLimits::depth(255).max_depth(), MAX_DEPTH);
+        let iteration = search_to_depth(STARTING_FEN, 0);
+        assert_eq!(iteration.depth, 1);
+        assert_eq!(iteration.move_scores.len(), 20);
+    }
+
+    // However large the weights, evaluations mustn't be mistaken for checkmates.
+    #[test]
+    fn evaluations_stay_below_mate_scores() {
+        let game_state = GameState::from_fen("4k3/8/8/8/8/8/8/QQQQK3 w - - 0 1").unwrap();
+        let evaluator = weighted_evaluator([i16::MAX, 0, 0], [i16::MAX; 5]);
+        let score = evaluator.evaluate(&game_state);
+        assert!(score > 0 && score < MIN_MATE_SCORE, "{}", score);
+        assert_eq!(moves_to_mate(score), None);
+    }
+
+    #[test]
+    fn time_budget_takes_the_smaller_limit() {
+        let limits = |movetime, time_left| SearchLimits {
+            movetime: Some(Duration::from_secs(movetime)),
+            time_left: Some(Duration::from_secs(time_left)),
+            moves_to_go: Some(10),
+            .. SearchLimits::default()
+        };
+        // Ten seconds a move, but only six left on the clock for ten moves.
+        assert_eq!(limits(10, 6).time_budget(), Some(Duration::from_millis(600)));
+        assert_eq!(limits(1, 600).time_budget(), Some(Duration::from_secs(1)));
+        assert_eq!(SearchLimits { infinite: true, .. limits(1, 600) }.time_budget(), None);
+    }
+
+    // Captures are ordered and pruned by what the evaluation counts the pieces as.
+    #[test]
+    fn weighted_piece_values() {
+        let evaluator = weighted_evaluator([10, 0, 0], [1, 20, 3, 5, 9]);
+        assert_eq!(evaluator.piece_value(Pawn), 10);
+        assert_eq!(evaluator.piece_value(Knight), 200);
+        assert_eq!(evaluator.piece_value(Queen), 90);
+        assert_eq!(evaluator.piece_value(King), i32::MAX);
+    }
+
+    #[test]
+    fn centipawn_scores() {
+        let evaluator = weighted_evaluator([15, 0, 0], DEFAULT_PIECE_VALUES);
+        assert_eq!(evaluator.centipawns(15), 100);
+        assert_eq!(evaluator.centipawns(-45), -300);
+        assert_eq!(evaluator.centipawns(7), 46);
+        let evaluator = Evaluator::new(Box::new(|_| 0), None);
+        assert_eq!(evaluator.centipawns(15), 15);
+    }
+
+    #[test]
+    fn mate_in_one() {
+        assert_mate_in("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 1);
+    }
+
+    #[test]
+    fn mate_in_two() {
+        assert_mate_in("7k/8/8/8/8/8/1R6/R3K3 w - - 0 1", 2);
+    }
+
+    #[test]
+    fn mate_in_three() {
+        assert_mate_in("8/6k1/8/8/8/8/8/RR4K1 w - - 0 1", 3);
+    }
+
+    #[test]
+    fn mate_for_black() {
+        assert_mate_in("r5k1/5ppp/8/8/8/8/5PPP/6K1 b - - 0 1", 1);
+    }
+
+    #[test]
+    fn being_mated_scores_negatively() {
+        // After 1. Rb7, Black can only delay 2. Ra8# by a move.
+        let iteration = search_to_depth("7k/1R6/8/8/8/8/8/R3K3 b - - 0 1", 3);
+        assert_eq!(moves_to_mate(iteration.move_scores[0].1), Some(-1));
+    }
+
+    #[test]
+    fn tied_best_moves_have_exact_scores() {
+        // Both rooks mate on the eighth rank, and no other move does.
+        let iteration = search_to_depth("6k1/5ppp/8/8/8/8/8/R2R2K1 w - - 0 1", 2);
+        let mating_moves = iteration.move_scores.iter()
+            .filter(|&&(_, score)| moves_to_mate(score) == Some(1))
+            .count();
+        assert_eq!(mating_moves, 2);
     }
 }
@@ -1,50 +1,226 @@
-use std::collections::HashMap;
-use std::collections::HashSet;
-
+use bitboard;
+use bitboard::Bitboard;
 use piece_type::PieceType;
 use position::Position;
 use piece_move::Move;
 use piece_move::ExtraCastlingMove;
+use zobrist;
+
+pub const STARTING_FEN: &'static str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
 #[derive(Clone)]
 pub struct GameState {
     pub current_player: Color,
     board: [[Option<Piece>; 8]; 8],
+    // The same pieces as the board, as a set of squares for each color and piece type. Both are
+    // kept up to date by set_piece.
+    pieces: [[Bitboard; 6]; 2],
+    occupancy: [Bitboard; 2],
     // Located here so we don't have to sweep the board of en passant targets after each turn.
     en_passant_target: Option<Position>,
-    previous_player_dests: HashSet<Position>,
-    pub previous_state_counts: HashMap<String, u8>,
+    // Plies since the last capture or pawn move.
+    halfmove_clock: u16,
+    // Starts at 1 and is incremented after each Black move.
+    fullmove_number: u16,
+    move_limit_rule: MoveLimitRule,
+    // Zobrist hash of the position, kept up to date by set_piece and move_piece.
+    hash: u64,
+    // The hashes of every position reached so far, including the current one.
+    position_history: Vec<u64>,
 }
 
 impl GameState {
     pub fn opening_state() -> GameState {
-        // White on top so that (0,0) matches up with a1. Flipped for the actual display.
-        let raw_board =
-            [['R', 'N', 'B', 'Q', 'K', 'B', 'N', 'R']
-            ,['P', 'P', 'P', 'P', 'P', 'P', 'P', 'P']
-            ,['-', '-', '-', '-', '-', '-', '-', '-']
-            ,['-', '-', '-', '-', '-', '-', '-', '-']
-            ,['-', '-', '-', '-', '-', '-', '-', '-']
-            ,['-', '-', '-', '-', '-', '-', '-', '-']
-            ,['p', 'p', 'p', 'p', 'p', 'p', 'p', 'p']
-            ,['r', 'n', 'b', 'q', 'k', 'b', 'n', 'r']];
+        GameState::from_fen(STARTING_FEN).unwrap()
+    }
+
+    // Parses a position in Forsyth-Edwards Notation.
+    // Castling rights are stored on the King and Rook pieces themselves, and the FEN en passant
+    // square is converted to the position of the pawn that can be taken.
+    // The move counters are optional so that bare EPD-style positions can be loaded too.
+    pub fn from_fen(fen: &str) -> Option<GameState> {
+        let fields = fen.split_whitespace().collect::<Vec<_>>();
+        if fields.len() != 4 && fields.len() != 6 {
+            return None;
+        }
 
+        let ranks = fields[0].split('/').collect::<Vec<_>>();
+        if ranks.len() != 8 {
+            return None;
+        }
+
+        // FEN lists the eighth rank first, but row 0 is the first rank.
         let mut board = [[Option::None; 8]; 8];
+        for (i, rank) in ranks.iter().enumerate() {
+            let row = 7 - i;
+            let mut column = 0;
+            for c in rank.chars() {
+                match c.to_digit(10) {
+                    Some(empty_count) if empty_count >= 1 && empty_count <= 8 =>
+                        column += empty_count as usize,
+                    Some(_) => return None,
+                    None if "PNBRQKpnbrqk".contains(c) && column < 8 => {
+                        board[row][column] = c.to_piece(false);
+                        column += 1;
+                    },
+                    None => return None,
+                }
+            }
+
+            if column != 8 {
+                return None;
+            }
+        }
+
+        let current_player = match fields[1] {
+            "w" => Color::White,
+            "b" => Color::Black,
+            _   => return None,
+        };
+
+        if fields[2] != "-" {
+            for c in fields[2].chars() {
+                let (color, rook_column) = match c {
+                    'K' => (Color::White, 7),
+                    'Q' => (Color::White, 0),
+                    'k' => (Color::Black, 7),
+                    'q' => (Color::Black, 0),
+                    _   => return None,
+                };
+
+                let row = if color == Color::White { 0 } else { 7 };
+                for &(column, piece_type) in [(4, PieceType::King), (rook_column, PieceType::Rook)].iter() {
+                    match board[row][column] {
+                        Some(ref mut piece) if piece.piece_type == piece_type && piece.color == color =>
+                            piece.can_castle = true,
+                        _ => return None,
+                    }
+                }
+            }
+        }
+
+        let en_passant_target = if fields[3] == "-" {
+            None
+        } else {
+            // The pawn that can be taken sits one row past the square that it skipped over.
+            let (skipped_row, pawn_row, pawn_color) = match current_player {
+                Color::White => (5, 4, Color::Black),
+                Color::Black => (2, 3, Color::White),
+            };
+            match Position::from_notation(fields[3]) {
+                Some(ref skipped) if skipped.row == skipped_row => {
+                    let pawn_position = Position { column: skipped.column, row: pawn_row };
+                    match board[pawn_row as usize][skipped.column as usize] {
+                        Some(piece) if piece.piece_type == PieceType::Pawn && piece.color == pawn_color =>
+                            Some(pawn_position),
+                        _ => return None,
+                    }
+                },
+                _ => return None,
+            }
+        };
+
+        let (halfmove_clock, fullmove_number) = if fields.len() == 6 {
+            match (fields[4].parse(), fields[5].parse()) {
+                (Ok(halfmove_clock), Ok(fullmove_number)) if fullmove_number > 0 =>
+                    (halfmove_clock, fullmove_number),
+                _ => return None,
+            }
+        } else {
+            (0, 1)
+        };
+
+        let mut game_state = GameState {
+            board: [[None; 8]; 8],
+            pieces: [[0; 6]; 2],
+            occupancy: [0; 2],
+            current_player: current_player,
+            en_passant_target: en_passant_target,
+            halfmove_clock: halfmove_clock,
+            fullmove_number: fullmove_number,
+            move_limit_rule: MoveLimitRule::FiftyMove,
+            hash: 0,
+            position_history: vec![],
+        };
         for row in 0..8 {
             for column in 0..8 {
-                board[row][column] = raw_board[row][column].to_piece(true);
+                game_state.set_piece(&board[row][column], &Position { column: column as i8, row: row as i8 });
             }
         }
 
-        let mut previous_state_counts = HashMap::new();
-        previous_state_counts.insert(GameState::custom_hash(board), 1);
-        GameState {
-            board: board,
-            current_player: Color::White,
-            en_passant_target: Option::None,
-            previous_player_dests: HashSet::new(),
-            previous_state_counts: previous_state_counts,
+        // Every position that can come up in a game has one king a side, and the player who just moved
+        // can't have left theirs in check.
+        if game_state.count_pieces(PieceType::King, Color::White) != 1
+                || game_state.count_pieces(PieceType::King, Color::Black) != 1
+                || game_state.is_in_check(current_player.opposite()) {
+            return None;
         }
+
+        game_state.hash = game_state.compute_hash();
+        game_state.position_history.push(game_state.hash);
+        Some(game_state)
+    }
+
+    pub fn format_fen(&self) -> String {
+        let mut placement = String::new();
+        for row in (0..8).rev() {
+            let mut empty_count = 0;
+            for column in 0..8 {
+                match self.board[row][column] {
+                    None => empty_count += 1,
+                    Some(piece) => {
+                        if empty_count > 0 {
+                            placement.push_str(&empty_count.to_string());
+                            empty_count = 0;
+                        }
+
+                        placement.push(piece.to_fen_char());
+                    },
+                }
+            }
+
+            if empty_count > 0 {
+                placement.push_str(&empty_count.to_string());
+            }
+
+            if row > 0 {
+                placement.push('/');
+            }
+        }
+
+        let mut castling = [(Color::White, 7, 'K'), (Color::White, 0, 'Q'), (Color::Black, 7, 'k'), (Color::Black, 0, 'q')]
+            .iter()
+            .filter(|&&(color, rook_column, _)| self.has_castling_right(color, rook_column))
+            .map(|&(_, _, c)| c)
+            .collect::<String>();
+        if castling.is_empty() {
+            castling.push('-');
+        }
+
+        let en_passant = match self.en_passant_target {
+            None => "-".to_owned(),
+            // FEN records the square that the pawn skipped over rather than the pawn itself.
+            Some(ref target) => {
+                let direction = if self.current_player == Color::White { 1 } else { -1 };
+                target.relative(0, direction).format()
+            },
+        };
+
+        format!("{} {} {} {} {} {}",
+            placement,
+            if self.current_player == Color::White { "w" } else { "b" },
+            castling,
+            en_passant,
+            self.halfmove_clock,
+            self.fullmove_number)
+    }
+
+    fn has_castling_right(&self, color: Color, rook_column: i8) -> bool {
+        let row = if color == Color::White { 0 } else { 7 };
+        let is_castler = |column, piece_type| self.get_piece(&Position { column: column, row: row })
+            .map_or(false, |piece| piece.piece_type == piece_type && piece.color == color && piece.can_castle);
+
+        is_castler(4, PieceType::King) && is_castler(rook_column, PieceType::Rook)
     }
 
     pub fn format(&self) -> String {
@@ -63,49 +239,55 @@ impl GameState {
         result
     }
 
-    pub fn play_turn(
-            &mut self,
-            player_brain:
-                &Box<Fn(&GameState, &Vec<Move>, u8) -> Move>,
-            max_ai_depth: &u8) -> PlayerState {
-
-        let game_state = self.clone();
-        let player_state = game_state.get_player_moves();
-        if let PlayerState::CanMove(moves) = player_state.clone() {
-            let player_move = player_brain(&game_state, &moves, max_ai_depth.clone());
-            println!("{:?} played {}", game_state.current_player, player_move.simple_format());
-            self.move_piece(&player_move);
-        }
+    pub fn set_move_limit_rule(&mut self, move_limit_rule: MoveLimitRule) {
+        self.move_limit_rule = move_limit_rule;
+    }
 
-        player_state
+    pub fn fullmove_number(&self) -> u16 {
+        self.fullmove_number
     }
 
-    pub fn get_all_pieces(&self) -> Vec<Piece> {
-        let mut result = vec![];
-        for col in 0..8 {
-            for row in 0..8 {
-                if let Some(piece) = self.get_piece(&Position { column: col, row: row }) {
-                    result.push(piece);
-                }
-            }
-        }
-        
-        result
+    pub fn count_pieces(&self, piece_type: PieceType, color: Color) -> u32 {
+        self.pieces[color.index()][piece_type.index()].count_ones()
     }
 
-    fn get_piece(&self, position: &Position) -> Option<Piece> {
+    pub fn get_piece(&self, position: &Position) -> Option<Piece> {
         self.board[position.row as usize][position.column as usize]
     }
 
     fn set_piece(&mut self, piece: &Option<Piece>, position: &Position) {
+        let bit = bitboard::bit(position);
+        if let Some(old_piece) = self.get_piece(position) {
+            self.hash ^= zobrist::piece_key(old_piece.piece_type, old_piece.color, position);
+            self.pieces[old_piece.color.index()][old_piece.piece_type.index()] &= !bit;
+            self.occupancy[old_piece.color.index()] &= !bit;
+        }
+
+        if let Some(new_piece) = *piece {
+            self.hash ^= zobrist::piece_key(new_piece.piece_type, new_piece.color, position);
+            self.pieces[new_piece.color.index()][new_piece.piece_type.index()] |= bit;
+            self.occupancy[new_piece.color.index()] |= bit;
+        }
+
         self.board[position.row as usize][position.column as usize] = *piece;
     }
 
     pub fn move_piece(&mut self, player_move: &Move) {
+        // The castling rights and en passant parts of the hash are swapped out wholesale since
+        // a single move can affect them in several ways.
+        self.hash ^= self.castling_and_en_passant_hash();
+
         // En passant is only possible for the turn after it was enabled.
         self.en_passant_target = None;
 
         let mut source_piece = self.get_piece(&player_move.source).unwrap();
+        let is_capture = !self.is_empty(&player_move.destination) || player_move.en_passant_target.is_some();
+        if is_capture || source_piece.piece_type == PieceType::Pawn {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+
         if source_piece.piece_type == PieceType::King || source_piece.piece_type == PieceType::Rook {
             source_piece.can_castle = false;
         }
@@ -132,19 +314,149 @@ impl GameState {
             self.en_passant_target = Some(player_move.destination.clone());
         }
 
-        // Update the map of previous states
-        let hash = GameState::custom_hash(self.board);
-        if self.previous_state_counts.contains_key(&hash) {
-            *self.previous_state_counts.get_mut(&hash).unwrap() += 1;
-        } else {
-            self.previous_state_counts.insert(hash, 1);
-        }
-
         self.current_player = if self.current_player == Color::White {
             Color::Black
         } else {
             Color::White
         };
+
+        if self.current_player == Color::White {
+            self.fullmove_number += 1;
+        }
+
+        self.hash ^= self.castling_and_en_passant_hash() ^ zobrist::black_to_move_key();
+        self.position_history.push(self.hash);
+    }
+
+    // Plays a move in place, returning what unmake_move needs to take it back. Moves must be taken
+    // back in the reverse order that they were made.
+    pub fn make_move(&mut self, player_move: &Move) -> Undo {
+        let captured_position = player_move.en_passant_target.as_ref().unwrap_or(&player_move.destination);
+        let undo = Undo {
+            // Also records the castling rights of the moving King or Rook.
+            moved_piece: self.get_piece(&player_move.source).unwrap(),
+            // A captured Rook takes its castling right with it.
+            captured_piece: self.get_piece(captured_position),
+            en_passant_target: self.en_passant_target.clone(),
+            halfmove_clock: self.halfmove_clock,
+            hash: self.hash,
+        };
+
+        self.move_piece(player_move);
+        undo
+    }
+
+    pub fn unmake_move(&mut self, player_move: &Move, undo: &Undo) {
+        self.position_history.pop();
+
+        if self.current_player == Color::White {
+            self.fullmove_number -= 1;
+        }
+
+        self.current_player = self.current_player.opposite();
+
+        if let Some(ref extra_castling_move) = player_move.extra_castling_move {
+            let rook = self.get_piece(&extra_castling_move.destination);
+            self.set_piece(&rook, &extra_castling_move.source);
+            self.set_piece(&None, &extra_castling_move.destination);
+        }
+
+        self.set_piece(&None, &player_move.destination);
+        let captured_position = player_move.en_passant_target.as_ref().unwrap_or(&player_move.destination);
+        self.set_piece(&undo.captured_piece, captured_position);
+        self.set_piece(&Some(undo.moved_piece), &player_move.source);
+
+        self.en_passant_target = undo.en_passant_target.clone();
+        self.halfmove_clock = undo.halfmove_clock;
+        // The pieces above were set with the hash kept up to date, but castling rights and en
+        // passant are simplest to restore wholesale.
+        self.hash = undo.hash;
+    }
+
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    fn compute_hash(&mut self) -> u64 {
+        let mut hash = self.castling_and_en_passant_hash();
+        if self.current_player == Color::Black {
+            hash ^= zobrist::black_to_move_key();
+        }
+
+        for (piece, position) in self.to_vec() {
+            hash ^= zobrist::piece_key(piece.piece_type, piece.color, &position);
+        }
+
+        hash
+    }
+
+    fn castling_and_en_passant_hash(&mut self) -> u64 {
+        let mut hash = 0;
+        let castling_rights = [(Color::White, 7), (Color::White, 0), (Color::Black, 7), (Color::Black, 0)];
+        for (i, &(color, rook_column)) in castling_rights.iter().enumerate() {
+            if self.has_castling_right(color, rook_column) {
+                hash ^= zobrist::castling_key(i);
+            }
+        }
+
+        if let Some(column) = self.get_capturable_en_passant_column() {
+            hash ^= zobrist::en_passant_key(column);
+        }
+
+        hash
+    }
+
+    // Positions only differ by their en passant target if it can actually be taken, so that
+    // repetitions are detected exactly as the FIDE rules define them.
+    fn get_capturable_en_passant_column(&mut self) -> Option<i8> {
+        let target = match self.en_passant_target {
+            Some(ref target) => target.clone(),
+            None => return None,
+        };
+
+        let direction = if self.current_player == Color::White { 1 } else { -1 };
+        let dest = target.relative(0, direction);
+        let sources = [-1, 1].iter()
+            .filter_map(|&col_offset| self.relative(&target, col_offset, 0))
+            .filter(|source| self.get_piece(source).map_or(false, |piece|
+                piece.piece_type == PieceType::Pawn && piece.color == self.current_player))
+            .collect::<Vec<_>>();
+
+        let mut is_capturable = false;
+        for source in sources {
+            // Played out directly on the board since move_piece would need this hash.
+            let pawn = self.get_piece(&source);
+            let captured_pawn = self.get_piece(&target);
+            let hash = self.hash;
+            self.set_piece(&None, &source);
+            self.set_piece(&None, &target);
+            self.set_piece(&pawn, &dest);
+            is_capturable = !self.is_in_check(self.current_player);
+            self.set_piece(&None, &dest);
+            self.set_piece(&captured_pawn, &target);
+            self.set_piece(&pawn, &source);
+            self.hash = hash;
+
+            if is_capturable {
+                break;
+            }
+        }
+
+        if is_capturable {
+            Some(target.column)
+        } else {
+            None
+        }
+    }
+
+    // Only positions since the last capture or pawn move can be repeats, and only every other one
+    // of those has the same player to move.
+    fn is_threefold_repetition(&self) -> bool {
+        self.position_history.iter().rev()
+            .take(self.halfmove_clock as usize + 1)
+            .step_by(2)
+            .filter(|&&hash| hash == self.hash)
+            .count() >= 3
     }
 
     fn is_in_bounds(&self, position: &Position) -> bool {
@@ -163,35 +475,28 @@ impl GameState {
         self.get_piece(position).is_none()
     }
 
-    // Can't figure out how to get actual Rust hashing working.
-    // This function is too simple since it doesn't account for special states like en passant.
-    fn custom_hash(board: [[Option<Piece>; 8]; 8]) -> String {
-        let mut chars = vec![];
-        for i in 0..8 {
-            for j in 0..8 {
-                chars.push(board[i][j].map_or('-', |p| p.to_char()));
-            }
-        }
-
-        chars.into_iter().collect::<String>()
+    pub fn get_player_moves(&self) -> PlayerState {
+        self.clone().get_player_moves_in_place()
     }
 
-    pub fn get_player_moves(&self) -> PlayerState {
-        let moves = self.get_player_moves_base(self.current_player).into_iter()
-            .filter(|player_move| {
-                let mut game_state = self.clone();
-                game_state.move_piece(&player_move);
-                !game_state.is_in_check(self.current_player)
-            }).collect::<Vec<_>>();
+    // The same as get_player_moves, for callers that can lend out the state to avoid copying it.
+    pub fn get_player_moves_in_place(&mut self) -> PlayerState {
+        let moves = self.get_legal_moves_in_place();
 
+        if self.is_threefold_repetition() {
+            return PlayerState::Draw(DrawReason::ThreefoldRepetition);
+        }
 
-        // TODO: Make this visible to the AI rather than it needing to determine it separately.
-        if self.previous_state_counts.values().any(|&count| count >= 3) {
-            return PlayerState::Stalemate;
+        if self.is_insufficient_material() {
+            return PlayerState::Draw(DrawReason::InsufficientMaterial);
         }
 
         if !moves.is_empty() {
-            PlayerState::CanMove(moves)
+            // Checkmate takes precedence over the move limit, hence only checking it here.
+            match self.get_move_limit_draw() {
+                Some(draw_reason) => PlayerState::Draw(draw_reason),
+                None => PlayerState::CanMove(moves),
+            }
         } else if self.is_in_check(self.current_player) {
             PlayerState::Checkmate
         } else {
@@ -199,6 +504,67 @@ impl GameState {
         }
     }
 
+    // The moves that don't leave the current player in check, without regard to whether the game is
+    // already over by repetition.
+    pub fn get_legal_moves(&self) -> Vec<Move> {
+        // A single copy is played on and taken back rather than copying the state for every move.
+        self.clone().get_legal_moves_in_place()
+    }
+
+    // The same as get_legal_moves, for callers that can lend out the state to avoid copying it.
+    pub fn get_legal_moves_in_place(&mut self) -> Vec<Move> {
+        let player = self.current_player;
+        self.get_player_moves_base(player).into_iter()
+            .filter(|player_move| !self.leaves_in_check(player_move))
+            .collect::<Vec<_>>()
+    }
+
+    // Whether a move would leave the player making it in check.
+    pub fn leaves_in_check(&mut self, player_move: &Move) -> bool {
+        let player = self.current_player;
+        let undo = self.make_move(player_move);
+        let is_in_check = self.is_in_check(player);
+        self.unmake_move(player_move, &undo);
+        is_in_check
+    }
+
+    // Whether neither player could ever checkmate: K v K, K and a minor piece v K, or Kings with
+    // any number of bishops that are all on the same color of square.
+    fn is_insufficient_material(&self) -> bool {
+        let pieces = self.to_vec().into_iter()
+            .filter(|&(piece, _)| piece.piece_type != PieceType::King)
+            .collect::<Vec<_>>();
+
+        match pieces.as_slice() {
+            [] => true,
+            [(piece, _)] => piece.piece_type == PieceType::Knight || piece.piece_type == PieceType::Bishop,
+            [(_, ref first_position), ..] => pieces.iter().all(|&(piece, ref position)|
+                piece.piece_type == PieceType::Bishop
+                    && (position.column + position.row) % 2 == (first_position.column + first_position.row) % 2),
+        }
+    }
+
+    // Whether the player has too little to win if their opponent runs out of time: a lone King, or
+    // a King and a single minor piece, as in the USCF rules.
+    pub fn has_insufficient_material(&self, color: Color) -> bool {
+        let count = |piece_type| self.count_pieces(piece_type, color);
+        let minor_piece_count = count(PieceType::Knight) + count(PieceType::Bishop);
+        count(PieceType::Pawn) + count(PieceType::Rook) + count(PieceType::Queen) == 0 && minor_piece_count <= 1
+    }
+
+    fn get_move_limit_draw(&self) -> Option<DrawReason> {
+        let (ply_limit, draw_reason) = match self.move_limit_rule {
+            MoveLimitRule::FiftyMove => (100, DrawReason::FiftyMoveRule),
+            MoveLimitRule::SeventyFiveMove => (150, DrawReason::SeventyFiveMoveRule),
+        };
+
+        if self.halfmove_clock >= ply_limit {
+            Some(draw_reason)
+        } else {
+            None
+        }
+    }
+
     pub fn is_in_check(&self, player: Color) -> bool {
         let king_position = match self.find_piece(PieceType::King, player) {
             Some(kp) => kp,
@@ -209,28 +575,36 @@ impl GameState {
             }
         };
 
-        self.get_player_moves_without_check(player.opposite()).iter()
-            .any(|opponent_move| opponent_move.destination == king_position)
+        self.is_square_attacked(&king_position, player.opposite())
+    }
+
+    // Whether any piece of the specified color could take a piece on the position, regardless of
+    // whether doing so would leave its own king in check.
+    pub fn is_square_attacked(&self, position: &Position, by_color: Color) -> bool {
+        let square = bitboard::square(position);
+        let occupied = self.occupancy[0] | self.occupancy[1];
+        let attackers = &self.pieces[by_color.index()];
+        let queens = attackers[PieceType::Queen.index()];
+
+        // Pawns attack diagonally forward, so look diagonally backward from the position for them.
+        bitboard::pawn_attacks(by_color.opposite(), square) & attackers[PieceType::Pawn.index()] != 0
+            || bitboard::knight_attacks(square) & attackers[PieceType::Knight.index()] != 0
+            || bitboard::king_attacks(square) & attackers[PieceType::King.index()] != 0
+            || bitboard::bishop_attacks(square, occupied) & (attackers[PieceType::Bishop.index()] | queens) != 0
+            || bitboard::rook_attacks(square, occupied) & (attackers[PieceType::Rook.index()] | queens) != 0
     }
 
     fn find_piece(&self, piece_type: PieceType, player: Color) -> Option<Position> {
-        self.to_vec().into_iter()
-            .find(|&(piece, _)| piece.piece_type == piece_type && piece.color == player)
-            .map(|(_, position)| position)
+        bitboard::squares(self.pieces[player.index()][piece_type.index()]).next().map(bitboard::position)
     }
 
     fn to_vec(&self) -> Vec<(Piece, Position)> {
-        let mut piece_positions = vec![];
-        for row in 0..8 {
-            for column in 0..8 {
-                if let Some(piece) = self.board[row][column] {
-                    piece_positions.push(
-                        (piece, Position { column: column as i8, row: row as i8}));
-                }
-            }
-        }
-
-        piece_positions
+        bitboard::squares(self.occupancy[0] | self.occupancy[1])
+            .map(|square| {
+                let position = bitboard::position(square);
+                (self.get_piece(&position).unwrap(), position)
+            })
+            .collect()
     }
 
     pub fn get_player_moves_without_check(&self, color: Color) -> Vec<Move> {
@@ -239,14 +613,8 @@ impl GameState {
 
     fn get_player_moves_base(&self, color: Color) -> Vec<Move> {
         let mut moves = vec![];
-        for row in 0..8 {
-            for column in 0..8 {
-                let position = &Position { column: column, row: row };
-                let piece = self.get_piece(position);
-                if piece.map_or(false, |p| p.color == color) {
-                    moves.append(&mut self.get_moves_for_piece(position));
-                }
-            }
+        for square in bitboard::squares(self.occupancy[color.index()]) {
+            moves.append(&mut self.get_moves_for_piece(&bitboard::position(square)));
         }
 
         moves
@@ -259,8 +627,11 @@ impl GameState {
         }
 
         let piece = maybe_piece.unwrap();
+        let source_square = bitboard::square(source);
+        let occupied = self.occupancy[0] | self.occupancy[1];
+        let enemies = self.occupancy[piece.color.opposite().index()];
         let mut moves = vec![];
-        
+
         match piece.piece_type {
             PieceType::Pawn => {
                 let (direction, start_row, promotion_row) = match piece.color {
@@ -271,8 +642,7 @@ impl GameState {
                 if let Some(forward_one) = self.relative(&source, 0, direction) {
                     if self.get_occupation_status(&piece, &forward_one) == OccupationStatus::Empty {
                         if forward_one.row == promotion_row {
-                            // TODO: Allow promotion pieces other than the queen.
-                            moves.push(Move::promotion(source.clone(), forward_one, PieceType::Queen));
+                            moves.append(&mut promotions(source, &forward_one));
                         } else {
                             moves.push(Move::simple(source.clone(), forward_one));
                             if let Some(forward_two) = self.relative(source, 0, 2 * direction) {
@@ -288,25 +658,12 @@ impl GameState {
                 }
 
                 // Pawns can take pieces on diagonals immediately in front of them.
-                if let Some(left_attack) = self.relative(&source, -1, direction) {
-                    if self.get_occupation_status(&piece, &left_attack) == OccupationStatus::Enemy {
-                        if left_attack.row == promotion_row {
-                            // TODO: Allow promotion pieces other than the queen.
-                            moves.push(Move::promotion(source.clone(), left_attack, PieceType::Queen));
-                        } else {
-                            moves.push(Move::simple(source.clone(), left_attack));
-                        }
-                    }
-                }
-
-                if let Some(right_attack) = self.relative(&source, 1, direction) {
-                    if self.get_occupation_status(&piece, &right_attack) == OccupationStatus::Enemy {
-                        if right_attack.row == promotion_row {
-                            // TODO: Allow promotion pieces other than the queen.
-                            moves.push(Move::promotion(source.clone(), right_attack, PieceType::Queen));
-                        } else {
-                            moves.push(Move::simple(source.clone(), right_attack));
-                        }
+                let attacks = bitboard::pawn_attacks(piece.color, source_square) & enemies;
+                for attack in bitboard::squares(attacks).map(bitboard::position) {
+                    if attack.row == promotion_row {
+                        moves.append(&mut promotions(source, &attack));
+                    } else {
+                        moves.push(Move::simple(source.clone(), attack));
                     }
                 }
 
@@ -325,50 +682,27 @@ impl GameState {
                 }
             },
 
-            PieceType::Knight => {
-                moves.append(&mut self.get_consecutive_moves(
-                        source,
-                        &[(-2, -1), (-2, 1), (-1, -2), (-1, 2), (1, -2), (1, 2), (2, -1), (2, 1)],
-                        Some(1) 
-                        ));
-            },
-
-            PieceType::Bishop => {
-                moves.append(&mut self.get_consecutive_moves(
-                        source,
-                        &[(-1, -1), (-1, 1), (1, -1), (1, 1)],
-                        None
-                        ));
-            },
-
-            PieceType::Rook => {
-                moves.append(&mut self.get_consecutive_moves(
-                        source,
-                        &[(-1, 0), (0, -1), (0, 1), (1, 0)],
-                        None
-                        ));
-            },
-
-            PieceType::Queen => {
-                moves.append(&mut self.get_consecutive_moves(
-                        source,
-                        &[(-1,-1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)],
-                        None
-                        ));
-
-            },
+            PieceType::Knight => moves.append(&mut self.get_moves_to(source, bitboard::knight_attacks(source_square))),
+            PieceType::Bishop => moves.append(&mut self.get_moves_to(source,
+                bitboard::bishop_attacks(source_square, occupied))),
+            PieceType::Rook => moves.append(&mut self.get_moves_to(source,
+                bitboard::rook_attacks(source_square, occupied))),
+            PieceType::Queen => moves.append(&mut self.get_moves_to(source,
+                bitboard::queen_attacks(source_square, occupied))),
             PieceType::King => {
-                moves.append(&mut self.get_consecutive_moves(
-                        source,
-                        &[(-1,-1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)],
-                        Some(1)
-                        ));
-
-                if piece.can_castle {
+                moves.append(&mut self.get_moves_to(source, bitboard::king_attacks(source_square)));
+
+                // A King can't castle out of, through, or into check. The squares the rook alone
+                // passes over may be attacked though.
+                let opponent = piece.color.opposite();
+                let is_safe = |columns: &[i8], row: i8| columns.iter()
+                    .all(|&column| !self.is_square_attacked(&Position { column: column, row: row }, opponent));
+                if piece.can_castle && !self.is_square_attacked(source, opponent) {
                     let row = if piece.color == Color::White { 0 } else { 7 };
                     let left_rook_position = Position { column: 0, row: row };
                     if self.get_piece(&left_rook_position).map_or(false, |piece| piece.can_castle)
-                            && self.are_all_empty(&[(1,row), (2,row), (3,row)]) {
+                            && self.are_all_empty(&[(1,row), (2,row), (3,row)])
+                            && is_safe(&[2, 3], row) {
 
                         let rook_move = ExtraCastlingMove {
                             source: source.relative(-4, 0),
@@ -379,7 +713,8 @@ impl GameState {
                     }
 
                     if self.get_piece(&Position { column: 7, row: row}).map_or(false, |piece| piece.can_castle)
-                            && self.are_all_empty(&[(5,row), (6,row)]) {
+                            && self.are_all_empty(&[(5,row), (6,row)])
+                            && is_safe(&[5, 6], row) {
                         let rook_move = ExtraCastlingMove {
                             source: source.relative(3, 0),
                             destination: source.relative(1, 0),
@@ -394,44 +729,12 @@ impl GameState {
         moves
     }
 
-    fn get_consecutive_moves(
-            &self,
-            source: &Position,
-            dirs: &[(i8, i8)],
-            max_moves: Option<u8>) -> Vec<Move> {
-
-        let source_piece = &self.get_piece(source).unwrap();
-
-        let mut moves = vec![];
-
-        for &(col_dir, row_dir) in dirs {
-            if col_dir == 0 && row_dir == 0 {
-                panic!("at least one dir must be non-zero");
-            }
-
-            let mut current_dir_moves = vec![];
-            let mut dest = source.clone();
-            loop {
-                dest = dest.relative(col_dir, row_dir);
-                if !self.is_in_bounds(&dest)
-                        || max_moves.map_or(false, |max| current_dir_moves.len() >= max as usize) {
-                    break;
-                }
-
-                match self.get_occupation_status(source_piece, &dest) {
-                    OccupationStatus::Friendly => break,
-                    OccupationStatus::Empty => current_dir_moves.push(Move::simple(source.clone(), dest.clone())),
-                    OccupationStatus::Enemy => {
-                        current_dir_moves.push(Move::simple(source.clone(), dest.clone()));
-                        break;
-                    },
-                }
-            }
-
-            moves.append(&mut current_dir_moves);
-        }
-
-        moves
+    // Simple moves from the source to each of the destinations not occupied by its own pieces.
+    fn get_moves_to(&self, source: &Position, destinations: Bitboard) -> Vec<Move> {
+        let own_pieces = self.occupancy[self.get_piece(source).unwrap().color.index()];
+        bitboard::squares(destinations & !own_pieces)
+            .map(|square| Move::simple(source.clone(), bitboard::position(square)))
+            .collect()
     }
 
     fn are_all_empty(&self, positions: &[(i8, i8)]) -> bool {
@@ -453,30 +756,43 @@ impl GameState {
         }
     }
 
-    pub fn get_end_state(&self, moves: &Vec<Move>) -> EndState {
-        if self.previous_state_counts.values().any(|&count| count >= 3) {
-            return EndState::Stalemate;
-        }
+}
 
-        if !self.get_all_pieces().iter()
-                .any(|piece| piece.color == self.current_player && piece.piece_type == PieceType::King) {
-            // The King has been taken.
-            return EndState::Win(self.current_player.opposite());
-        }
+fn promotions(source: &Position, destination: &Position) -> Vec<Move> {
+    [PieceType::Queen, PieceType::Rook, PieceType::Bishop, PieceType::Knight].iter()
+        .map(|&piece_type| Move::promotion(source.clone(), destination.clone(), piece_type))
+        .collect()
+}
 
-        if moves.is_empty() {
-            return EndState::Stalemate;
-        }
+// What make_move changed that can't be recovered from the move itself.
+#[derive(Clone)]
+pub struct Undo {
+    moved_piece: Piece,
+    captured_piece: Option<Piece>,
+    en_passant_target: Option<Position>,
+    halfmove_clock: u16,
+    hash: u64,
+}
 
-        EndState::NotEnded
-    }
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum DrawReason {
+    // Reached once a hundred plies have passed without a capture or pawn move, at which point all
+    // players are assumed to claim the draw.
+    FiftyMoveRule,
+    // The automatic draw after a hundred and fifty plies without a capture or pawn move.
+    SeventyFiveMoveRule,
+    // Neither player has enough pieces left to deliver checkmate.
+    InsufficientMaterial,
+    // The same position has occurred three times with the same player to move.
+    ThreefoldRepetition,
 }
 
-#[derive(PartialEq)]
-pub enum EndState {
-    NotEnded,
-    Win(Color),
-    Stalemate,
+// Whether games are drawn as soon as the fifty-move rule can be claimed, or only when the
+// seventy-five-move rule forces it.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum MoveLimitRule {
+    FiftyMove,
+    SeventyFiveMove,
 }
 
 #[derive(PartialEq)]
@@ -494,6 +810,14 @@ pub struct Piece {
 }
 
 impl Piece {
+    fn to_fen_char(&self) -> char {
+        if self.color == Color::White {
+            self.piece_type.letter()
+        } else {
+            self.piece_type.letter().to_ascii_lowercase()
+        }
+    }
+
     fn to_char(&self) -> char {
         let result = match (self.piece_type, self.color) {
             (PieceType::Pawn  , Color::White) => '♟',
@@ -559,12 +883,20 @@ pub enum Color {
 }
 
 impl Color {
-    fn opposite(&self) -> Color {
+    pub fn opposite(&self) -> Color {
         match *self {
             Color::White => Color::Black,
             Color::Black => Color::White,
         }
     }
+
+    // Used to index tables that have an entry for each color.
+    pub fn index(&self) -> usize {
+        match *self {
+            Color::White => 0,
+            Color::Black => 1,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -572,5 +904,317 @@ pub enum PlayerState {
     CanMove(Vec<Move>),
     Checkmate,
     Stalemate,
+    Draw(DrawReason),
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn castling_moves(fen: &str) -> Vec<String> {
+        let mut moves = GameState::from_fen(fen).unwrap().get_legal_moves().into_iter()
+            .filter(|m| m.extra_castling_move.is_some())
+            .map(|m| m.simple_format())
+            .collect::<Vec<_>>();
+        moves.sort();
+        moves
+    }
+
+    fn assert_fen_round_trip(fen: &str) {
+        assert_eq!(GameState::from_fen(fen).unwrap().format_fen(), fen);
+    }
+
+    #[test]
+    fn fen_round_trip() {
+        assert_fen_round_trip(STARTING_FEN);
+        assert_fen_round_trip("r3k2r/8/8/8/8/8/8/R3K2R w Kq - 0 1");
+        assert_fen_round_trip("r3k2r/8/8/8/8/8/8/R3K2R b Qk - 0 1");
+        assert_fen_round_trip("r3k2r/8/8/8/8/8/8/R3K2R w - - 0 1");
+        assert_fen_round_trip("rnbqkbnr/pppp1ppp/8/8/3Pp3/8/PPP1PPPP/RNBQKBNR b KQkq d3 0 2");
+        assert_fen_round_trip("rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3");
+        assert_fen_round_trip("8/5k2/8/8/8/8/2K5/8 b - - 37 102");
+    }
+
+    #[test]
+    fn fen_without_counters() {
+        assert_eq!(GameState::from_fen("8/5k2/8/8/8/8/2K5/8 w - -").unwrap().format_fen(),
+            "8/5k2/8/8/8/8/2K5/8 w - - 0 1");
+    }
+
+    #[test]
+    fn malformed_fen() {
+        for fen in [
+            "",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1",
+            "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/ppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 0",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - x 1",
+            // Castling rights without the king and rook in place.
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBN1 w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkx - 0 1",
+            // An en passant square with no pawn that could have skipped over it.
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq e3 0 1",
+            "rnbqkbnr/pppp1ppp/8/8/3Pp3/8/PPP1PPPP/RNBQKBNR w KQkq d3 0 2",
+            // Missing and extra kings.
+            "8/8/8/8/8/8/8/8 w - - 0 1",
+            "8/5k2/8/8/8/8/8/8 w - - 0 1",
+            "8/5k2/8/8/8/8/2K5/4K3 w - - 0 1",
+            // The player who just moved has left their king in check.
+            "4k3/8/8/8/8/8/8/4K2r b - - 0 1",
+        ].iter() {
+            assert!(GameState::from_fen(fen).is_none(), "{}", fen);
+        }
+
+        // The player to move can be in check, though.
+        assert!(GameState::from_fen("4k3/8/8/8/8/8/8/4K2r w - - 0 1").is_some());
+    }
+
+    #[test]
+    fn castling_both_sides() {
+        assert_eq!(castling_moves("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1"), vec!["e1c1", "e1g1"]);
+        assert_eq!(castling_moves("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1"), vec!["e8c8", "e8g8"]);
+    }
+
+    #[test]
+    fn castling_requires_rights() {
+        assert_eq!(castling_moves("r3k2r/8/8/8/8/8/8/R3K2R w Kq - 0 1"), vec!["e1g1"]);
+        assert_eq!(castling_moves("r3k2r/8/8/8/8/8/8/R3K2R w - - 0 1"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn castling_rights_lost_after_king_or_rook_moves() {
+        let mut game_state = GameState::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        game_state.move_piece(&Move::from_notation("h1h2").unwrap());
+        game_state.move_piece(&Move::from_notation("e8d8").unwrap());
+        game_state.move_piece(&Move::from_notation("h2h1").unwrap());
+        game_state.move_piece(&Move::from_notation("d8e8").unwrap());
+        assert_eq!(game_state.format_fen(), "r3k2r/8/8/8/8/8/8/R3K2R w Q - 4 3");
+        assert_eq!(castling_moves(&game_state.format_fen()), vec!["e1c1"]);
+    }
+
+    #[test]
+    fn castling_blocked_by_pieces() {
+        assert_eq!(castling_moves("4k3/8/8/8/8/8/8/RN2K1NR w KQ - 0 1"), Vec::<String>::new());
+        // Even the square next to the queenside rook must be empty.
+        assert_eq!(castling_moves("4k3/8/8/8/8/8/8/RN2K2R w KQ - 0 1"), vec!["e1g1"]);
+    }
+
+    #[test]
+    fn no_castling_out_of_check() {
+        assert_eq!(castling_moves("4k3/8/8/8/4r3/8/8/R3K2R w KQ - 0 1"), Vec::<String>::new());
+        assert_eq!(castling_moves("4k3/8/8/8/8/8/5p2/R3K2R w KQ - 0 1"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn no_castling_through_check() {
+        assert_eq!(castling_moves("4k3/8/8/8/5r2/8/8/R3K2R w KQ - 0 1"), vec!["e1c1"]);
+        assert_eq!(castling_moves("4k3/8/8/8/3r4/8/8/R3K2R w KQ - 0 1"), vec!["e1g1"]);
+        assert_eq!(castling_moves("r3k2r/8/4N3/8/8/8/8/4K3 b kq - 0 1"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn no_castling_into_check() {
+        assert_eq!(castling_moves("4k3/8/8/8/8/8/6p1/R3K2R w KQ - 0 1"), vec!["e1c1"]);
+        assert_eq!(castling_moves("4k3/8/8/8/8/8/1b6/R3K2R w KQ - 0 1"), vec!["e1g1"]);
+    }
+
+    #[test]
+    fn castling_allowed_when_only_rook_squares_are_attacked() {
+        assert_eq!(castling_moves("4k2r/8/8/8/8/8/8/R3K2R w KQ - 0 1"), vec!["e1c1", "e1g1"]);
+        assert_eq!(castling_moves("1r2k3/8/8/8/8/8/8/R3K2R w KQ - 0 1"), vec!["e1c1", "e1g1"]);
+    }
+
+    // Checks every line of moves to the given depth: the incrementally updated hash has to match one
+    // computed from scratch after each move, and unmake_move has to restore everything make_move
+    // changed.
+    fn assert_make_and_unmake(game_state: &mut GameState, depth: u8) {
+        let mut fresh = game_state.clone();
+        assert_eq!(game_state.hash, fresh.compute_hash(), "{}", game_state.format_fen());
+        if depth == 0 {
+            return;
+        }
+
+        let fen = game_state.format_fen();
+        let (hash, history, pieces, occupancy) =
+            (game_state.hash, game_state.position_history.clone(), game_state.pieces, game_state.occupancy);
+        for player_move in game_state.get_legal_moves() {
+            let undo = game_state.make_move(&player_move);
+            assert_make_and_unmake(game_state, depth - 1);
+            game_state.unmake_move(&player_move, &undo);
+
+            let context = format!("{} after {}", fen, player_move.simple_format());
+            assert_eq!(game_state.format_fen(), fen, "{}", context);
+            assert_eq!(game_state.hash, hash, "{}", context);
+            assert_eq!(game_state.position_history, history, "{}", context);
+            assert_eq!(game_state.pieces, pieces, "{}", context);
+            assert_eq!(game_state.occupancy, occupancy, "{}", context);
+        }
+    }
+
+    #[test]
+    fn make_and_unmake_keep_the_hash() {
+        for fen in [
+            // Castling, captures of castling rooks and pawns that can then be taken en passant.
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            // Promotions, with and without captures.
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+            // En passant, including a capture that is pinned along the rank.
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "8/8/8/K2pP2r/8/8/8/7k w - d6 0 2",
+        ].iter() {
+            assert_make_and_unmake(&mut GameState::from_fen(fen).unwrap(), 3);
+        }
+    }
+
+    // Following the FIDE rules, an en passant square only counts towards the position when the pawn
+    // can really be taken.
+    #[test]
+    fn hash_only_includes_capturable_en_passant() {
+        let hash = |fen: &str| GameState::from_fen(fen).unwrap().hash();
+        assert_eq!(hash("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"),
+            hash("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"));
+        assert!(hash("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3")
+            != hash("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq - 0 3"));
+        // Taking d6 en passant would expose the King to the Rook.
+        assert_eq!(hash("8/8/8/K2pP2r/8/8/8/7k w - d6 0 2"), hash("8/8/8/K2pP2r/8/8/8/7k w - - 0 2"));
+    }
+
+    fn play(game_state: &mut GameState, moves: &[&str]) {
+        for notation in moves {
+            game_state.move_piece(&Move::from_notation(notation).unwrap());
+        }
+    }
+
+    fn draw_reason(game_state: &GameState) -> Option<DrawReason> {
+        match game_state.get_player_moves() {
+            PlayerState::Draw(draw_reason) => Some(draw_reason),
+            _ => None,
+        }
+    }
+
+    fn is_threefold_draw(game_state: &GameState) -> bool {
+        draw_reason(game_state) == Some(DrawReason::ThreefoldRepetition)
+    }
+
+    #[test]
+    fn threefold_repetition() {
+        let mut game_state = GameState::opening_state();
+        play(&mut game_state, &["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1"]);
+        assert!(!is_threefold_draw(&game_state));
+        play(&mut game_state, &["f6g8"]);
+        assert!(is_threefold_draw(&game_state));
+    }
+
+    // The position after the double step repeats, since the pawn it skipped over could never be taken.
+    #[test]
+    fn threefold_repetition_ignores_uncapturable_en_passant() {
+        let mut game_state = GameState::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        play(&mut game_state, &["e2e4", "e8d8", "e1d1", "d8e8", "d1e1", "e8d8", "e1d1", "d8e8"]);
+        assert!(!is_threefold_draw(&game_state));
+        play(&mut game_state, &["d1e1"]);
+        assert!(is_threefold_draw(&game_state));
+    }
+
+    // A capture or pawn move means that no earlier position can be repeated.
+    #[test]
+    fn threefold_repetition_resets_after_pawn_moves() {
+        let mut game_state = GameState::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        play(&mut game_state, &["e1d1", "e8d8", "d1e1", "d8e8", "e1d1", "e8d8", "d1e1", "d8e8", "e2e3"]);
+        assert!(!is_threefold_draw(&game_state));
+        play(&mut game_state, &["e8d8", "e1d1", "d8e8", "d1e1"]);
+        assert!(!is_threefold_draw(&game_state));
+    }
+
+    #[test]
+    fn fifty_move_rule() {
+        let mut game_state = GameState::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 98 80").unwrap();
+        play(&mut game_state, &["e1d1"]);
+        assert_eq!(draw_reason(&game_state), None);
+        play(&mut game_state, &["e8d8"]);
+        assert_eq!(draw_reason(&game_state), Some(DrawReason::FiftyMoveRule));
+    }
+
+    #[test]
+    fn seventy_five_move_rule() {
+        let mut game_state = GameState::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 100 80").unwrap();
+        game_state.set_move_limit_rule(MoveLimitRule::SeventyFiveMove);
+        assert_eq!(draw_reason(&game_state), None);
+
+        let mut game_state = GameState::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 149 80").unwrap();
+        game_state.set_move_limit_rule(MoveLimitRule::SeventyFiveMove);
+        assert_eq!(draw_reason(&game_state), None);
+        play(&mut game_state, &["e1d1"]);
+        assert_eq!(draw_reason(&game_state), Some(DrawReason::SeventyFiveMoveRule));
+    }
+
+    #[test]
+    fn move_limit_reset_by_pawn_moves_and_captures() {
+        let mut game_state = GameState::from_fen("4k3/8/8/8/8/8/4P3/R3K3 w - - 99 80").unwrap();
+        play(&mut game_state, &["e2e3"]);
+        assert_eq!(draw_reason(&game_state), None);
+        assert_eq!(game_state.halfmove_clock, 0);
+
+        let mut game_state = GameState::from_fen("4k3/8/8/8/8/8/r7/R3K3 w - - 99 80").unwrap();
+        play(&mut game_state, &["a1a2"]);
+        assert_eq!(draw_reason(&game_state), None);
+        assert_eq!(game_state.halfmove_clock, 0);
+    }
+
+    #[test]
+    fn checkmate_beats_the_move_limit() {
+        let mut game_state = GameState::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 99 80").unwrap();
+        play(&mut game_state, &["a1a8"]);
+        assert_eq!(game_state.halfmove_clock, 100);
+        match game_state.get_player_moves() {
+            PlayerState::Checkmate => (),
+            _ => panic!("The mate on the hundredth halfmove wasn't counted"),
+        }
+    }
+
+    fn is_insufficient_material_draw(fen: &str) -> bool {
+        draw_reason(&GameState::from_fen(fen).unwrap()) == Some(DrawReason::InsufficientMaterial)
+    }
+
+    #[test]
+    fn insufficient_material() {
+        // King against king, and king and a single minor piece against king.
+        assert!(is_insufficient_material_draw("8/8/4k3/8/8/3K4/8/8 w - - 0 1"));
+        assert!(is_insufficient_material_draw("8/8/4k3/8/8/3K4/8/6N1 w - - 0 1"));
+        assert!(is_insufficient_material_draw("8/8/4k3/8/8/3K4/8/2B5 b - - 0 1"));
+        // Bishops on squares of the same colour can never attack a king on the other colour.
+        assert!(is_insufficient_material_draw("5b2/8/4k3/8/8/3K4/8/2B5 w - - 0 1"));
+        assert!(!is_insufficient_material_draw("2b5/8/4k3/8/8/3K4/8/2B5 w - - 0 1"));
+        // Two knights can mate if the other side blunders.
+        assert!(!is_insufficient_material_draw("8/8/4k3/8/8/3K4/8/1N4N1 w - - 0 1"));
+        for fen in [
+            "8/8/4k3/8/8/3K4/4P3/8 w - - 0 1",
+            "8/8/4k3/8/8/3K4/8/7R w - - 0 1",
+            "8/8/4k3/8/8/3K4/8/3q4 w - - 0 1",
+            "8/8/4k3/4p3/8/3K4/8/2B5 w - - 0 1",
+        ].iter() {
+            assert!(!is_insufficient_material_draw(fen), "{}", fen);
+        }
+    }
+
+    // Used when a player runs out of time, where only the opponent's pieces matter.
+    #[test]
+    fn insufficient_material_to_win_on_time() {
+        let has_insufficient_material = |fen: &str, color| {
+            GameState::from_fen(fen).unwrap().has_insufficient_material(color)
+        };
+        assert!(has_insufficient_material("8/8/4k3/8/8/3K4/8/8 w - - 0 1", Color::White));
+        assert!(has_insufficient_material("8/8/4k3/8/8/3K4/8/2B5 w - - 0 1", Color::White));
+        assert!(has_insufficient_material("8/8/4k3/8/8/3K4/8/1N6 w - - 0 1", Color::White));
+        assert!(has_insufficient_material("8/8/4k3/8/8/3K4/8/Q7 w - - 0 1", Color::Black));
+        assert!(!has_insufficient_material("8/8/4k3/8/8/3K4/8/Q7 w - - 0 1", Color::White));
+        assert!(!has_insufficient_material("8/8/4k3/8/8/3K4/8/1N4N1 w - - 0 1", Color::White));
+        assert!(!has_insufficient_material("8/8/4k3/8/8/3K4/8/2B2B2 w - - 0 1", Color::White));
+        assert!(!has_insufficient_material("8/8/4k3/8/8/3K4/4P3/8 w - - 0 1", Color::White));
+        assert!(!has_insufficient_material("8/8/4k3/8/8/3K4/8/7R w - - 0 1", Color::White));
+    }
+}
@@ -2,24 +2,135 @@ use std::io;
 
 use piece_move::Move;
 use game_state::GameState;
+use computer_player::SearchLimits;
+use player::Player;
 
-pub fn human_player(_: &GameState, moves: &Vec<Move>, _: u8) -> Move {
-    loop {
-        println!("Enter a move:");
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
-        input = input.trim_right_matches('\n').to_owned();
-        match Move::from_notation(&input) {
-            None => println!("Invalid move"),
-            Some(player_move) => {
-                match moves.iter().find(|m| m.source == player_move.source
-                                        && m.destination == player_move.destination) {
-                    None => println!("Illegal move"),
-                    Some(result) => {
-                        return result.clone();
+// Reads moves from standard input. Typing "draw" offers a draw along with the next move, and
+// "resign" resigns. Reaching the end of the input resigns too, since no more moves can come.
+pub struct HumanPlayer {
+    offering_draw: bool,
+    resigning: bool,
+}
+
+impl HumanPlayer {
+    pub fn new() -> HumanPlayer {
+        HumanPlayer { offering_draw: false, resigning: false }
+    }
+}
+
+impl Player for HumanPlayer {
+    fn name(&self) -> &str {
+        "human"
+    }
+
+    fn choose_move(&mut self, game_state: &GameState, moves: &[Move], _: &SearchLimits) -> Move {
+        loop {
+            println!("Enter a move (SAN such as Nf3, or coordinates such as g1f3 or e7e8n), \"draw\" to offer \
+                a draw with your move, or \"resign\":");
+            let input = match read_input() {
+                Some(input) => input,
+                None => return self.resign(moves),
+            };
+            match input.as_str() {
+                "draw" => {
+                    self.offering_draw = true;
+                    println!("You'll offer a draw once you've moved.");
+                    continue;
+                },
+                "resign" => return self.resign(moves),
+                _ => (),
+            }
+
+            let player_move = match Move::from_notation(&input) {
+                Some(player_move) => {
+                    // Coordinates without a promotion piece match each of the possible promotions.
+                    let candidates = moves.iter()
+                        .filter(|m| m.source == player_move.source && m.destination == player_move.destination)
+                        .filter(|m| player_move.promotion_piece_type.is_none()
+                            || m.promotion_piece_type == player_move.promotion_piece_type)
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    match candidates.len() {
+                        0 => None,
+                        1 => candidates.into_iter().next(),
+                        _ => match choose_promotion(&candidates) {
+                            Some(choice) => Some(choice),
+                            None => return self.resign(moves),
+                        },
                     }
-                }
-            },
+                },
+                None => Move::from_san(&input, game_state).filter(|m| moves.contains(m)),
+            };
+
+            match player_move {
+                None => println!("Invalid or illegal move"),
+                Some(result) => return result,
+            };
+        }
+    }
+
+    fn new_game(&mut self) {
+        self.offering_draw = false;
+        self.resigning = false;
+    }
+
+    fn resigns(&mut self, _game_state: &GameState) -> bool {
+        self.resigning
+    }
+
+    fn offers_draw(&mut self, _game_state: &GameState) -> bool {
+        let offering_draw = self.offering_draw;
+        self.offering_draw = false;
+        offering_draw
+    }
+
+    fn accepts_draw(&mut self, _game_state: &GameState) -> bool {
+        loop {
+            println!("Your opponent offers a draw. Accept? (y/n)");
+            // At the end of the input the offer is declined, and the next move resigns.
+            let input = match read_input() {
+                Some(input) => input,
+                None => return false,
+            };
+            match input.to_lowercase().as_str() {
+                "y" | "yes" => return true,
+                "n" | "no" => return false,
+                _ => (),
+            }
+        }
+    }
+}
+
+impl HumanPlayer {
+    // Any move will do, since it isn't played.
+    fn resign(&mut self, moves: &[Move]) -> Move {
+        self.resigning = true;
+        moves[0].clone()
+    }
+}
+
+// A trimmed line of input, or None once there is no more to read.
+fn read_input() -> Option<String> {
+    let mut input = String::new();
+    match io::stdin().read_line(&mut input) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(input.trim().to_owned()),
+    }
+}
+
+fn choose_promotion(promotions: &[Move]) -> Option<Move> {
+    loop {
+        println!("Promote to which piece? Options: {:?}",
+            promotions.iter().filter_map(|m| m.promotion_piece_type).collect::<Vec<_>>());
+        let text = match read_input() {
+            Some(input) => input.to_lowercase(),
+            None => return None,
         };
+        let choice = promotions.iter().find(|m| m.promotion_piece_type.map_or(false, |piece_type|
+            text == piece_type.letter().to_ascii_lowercase().to_string()
+                || text == format!("{:?}", piece_type).to_lowercase()));
+        if let Some(choice) = choice {
+            return Some(choice.clone());
+        }
     }
 }
@@ -3,219 +3,222 @@
 extern crate rand;
 
 mod piece_type;
+mod bitboard;
 mod position;
 mod piece_move;
 mod game_state;
 mod human_player;
 mod computer_player;
-
-use std::collections::HashMap;
-
+mod perft;
+mod uci;
+mod pgn;
+mod zobrist;
+mod transposition_table;
+mod clock;
+mod player;
+mod ai_config;
+mod random;
+mod tournament;
+mod tuning;
+mod texel;
+mod cli;
+mod analysis;
+mod report;
+
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::process;
+
+use cli::Arguments;
 use game_state::GameState;
-use game_state::PlayerState;
-use human_player::human_player;
-use computer_player::piece_score_comp;
-use computer_player::max_moves_comp;
-use computer_player::max_spaces_comp;
-use computer_player::spaces_moves_comp;
-use piece_move::Move;
-
-// TODO Determine this from the players HashMap.
-const AI_COUNT: usize = 4;
+use game_state::MoveLimitRule;
+use game_state::STARTING_FEN;
+use human_player::HumanPlayer;
+use computer_player::MAX_DEPTH;
+use computer_player::SearchLimits;
+use player::Player;
+use player::PlayerRegistry;
+use pgn::PgnGame;
+use clock::TimeControl;
+use random::Random;
+use report::TournamentReport;
+use tournament::GameSettings;
+
+// Black's player in single games, unless another is chosen.
+const DEFAULT_OPPONENT: &'static str = "spaces_moves";
 
 fn main() {
-    let mut players: HashMap<String, Box<Fn(&GameState, &Vec<Move>, u8) -> Move>> = HashMap::new();
-
-    // TODO Unify name as seen here with the value in computer_player.rs.
-    players.insert("piece_score".to_owned(), Box::new(piece_score_comp));
-    players.insert("max_moves".to_owned(), Box::new(max_moves_comp));
-    players.insert("max_spaces".to_owned(), Box::new(max_spaces_comp));
-    players.insert("spaces_moves".to_owned(), Box::new(spaces_moves_comp));
-
-    let mut modes = HashMap::new();
-    modes.insert("single".to_owned(), GameMode::SingleGame);
-    modes.insert("AIs".to_owned(), GameMode::AIRoundRobin);
-
-    let stdin = std::io::stdin();
-    let mut mode_text = String::new();
-    println!("Mode? Options: {:?}", modes.keys().collect::<Vec<_>>());
-    stdin.read_line(&mut mode_text).unwrap();
-
-    let mut max_ai_depth_text = String::new();
-    println!("Max AI Depth?");
-    stdin.read_line(&mut max_ai_depth_text).unwrap();
-    let ref max_ai_depth = max_ai_depth_text.trim().parse().unwrap(); 
-
-    let ref mode = modes[&mode_text.trim().to_owned()]; 
-    match *mode {
-        GameMode::SingleGame => {
-            players.insert("human".to_owned(), Box::new(human_player));
-            play_single_game(players, max_ai_depth)
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let arguments = match cli::parse_arguments(&args) {
+        Ok(arguments) => arguments,
+        Err(error) => {
+            eprintln!("{}. Run \"chess help\" for the commands and their options.", error);
+            process::exit(2);
         },
-        GameMode::AIRoundRobin => {
-            println!("How many rounds per match?");
-            let mut rounds_per_match = String::new();
-            stdin.read_line(&mut rounds_per_match).unwrap();
-            play_ai_round_robin(players, rounds_per_match.trim().parse().unwrap(), max_ai_depth);
-        }
+    };
+
+    let result = match arguments.command.as_str() {
+        "play" => play_single_game(&arguments),
+        "tournament" => play_ai_round_robin(&arguments),
+        "perft" => run_perft(&arguments),
+        "analyze" => read_search_limits(&arguments).and_then(|limits| analysis::run_analysis(&arguments, limits)),
+        "tune" => tuning::run_tuning(&arguments),
+        "texel" => texel::run_texel_tuning(&arguments),
+        "uci" => {
+            uci::run_uci();
+            Ok(())
+        },
+        _ => {
+            print!("{}", cli::usage());
+            Ok(())
+        },
+    };
+
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        process::exit(1);
     }
 }
 
-enum GameMode {
-    SingleGame,
-    AIRoundRobin,
-}
+fn read_game_settings(arguments: &Arguments) -> Result<GameSettings, String> {
+    let time_control = match arguments.value("time-control") {
+        Some(text) => Some(TimeControl::parse(text).ok_or(format!("Invalid --time-control: {}", text))?),
+        None => None,
+    };
+    // The clocks set the limits in timed games.
+    if time_control.is_some() && (arguments.is_set("depth") || arguments.is_set("movetime")) {
+        return Err("--depth and --movetime can't be used with --time-control".to_owned());
+    }
 
-fn play_single_game(
-        players: HashMap<String, Box<Fn(&GameState, &Vec<Move>, u8) -> Move>>,
-        max_ai_depth: &u8) {
-
-    let stdin = std::io::stdin();
-    let mut player_1_text = String::new();
-    println!("What should player 1 be? Options: {:?}", players.keys().collect::<Vec<_>>());
-    stdin.read_line(&mut player_1_text).unwrap();
-    println!("{} player chosen.", &player_1_text.trim());
-    let ref player_1 = players[&player_1_text.trim().to_owned()];
-
-    let mut player_2_text = String::new();
-    println!("What should player 2 be? Options: {:?}", players.keys().collect::<Vec<_>>());
-    stdin.read_line(&mut player_2_text).unwrap();
-    println!("{} player chosen.", &player_2_text.trim());
-    let ref player_2 = players[&player_2_text.trim().to_owned()];
-    
-    play_game(player_1, player_2, max_ai_depth);
+    let move_limit_rule = match arguments.value("move-limit") {
+        None | Some("50") => MoveLimitRule::FiftyMove,
+        Some("75") => MoveLimitRule::SeventyFiveMove,
+        Some(text) => return Err(format!("Invalid --move-limit: {}", text)),
+    };
+
+    Ok(GameSettings {
+        time_control: time_control,
+        limits: read_search_limits(arguments)?,
+        move_limit_rule: move_limit_rule,
+        verbose: !arguments.is_set("quiet"),
+    })
 }
 
-fn play_ai_round_robin(
-        players: HashMap<String, Box<Fn(&GameState, &Vec<Move>, u8) -> Move>>,
-        rounds_per_match: u8,
-        max_ai_depth: &u8) {
-
-    let mut results = [[(0f32, 0f32, 0f32); AI_COUNT]; AI_COUNT];
-    for _ in 0..rounds_per_match {
-        for i in 0..AI_COUNT {
-            for j in 0..AI_COUNT {
-                if i == j {
-                    continue;
-                }
-
-                let ref white = players[players.keys().collect::<Vec<_>>()[i]];
-                let ref black = players[players.keys().collect::<Vec<_>>()[j]];
-                match play_game(&white, &black, max_ai_depth) {
-                    GameResult::WhiteWon => results[i][j].0 += 1.0,
-                    GameResult::BlackWon => results[i][j].1 += 1.0,
-                    GameResult::Draw     => results[i][j].2 += 1.0,
-                };
-            }
-        }
+// Either limit may be left out, and the search stops at whichever is reached first.
+fn read_search_limits(arguments: &Arguments) -> Result<SearchLimits, String> {
+    let depth = arguments.parse::<u8>("depth")?;
+    match depth {
+        Some(depth) if depth == 0 || depth > MAX_DEPTH =>
+            return Err(format!("--depth must be from 1 to {}", MAX_DEPTH)),
+        _ => (),
     }
 
-    // TODO:
-    // Display overall win and draw percentages
-    // Display per AI win and draw percentages
-    // AI ranking as white and black
-    let width = 16;
+    let movetime = match arguments.parse::<f64>("movetime")? {
+        Some(seconds) => match clock::duration_from_seconds(seconds) {
+            Some(movetime) if seconds > 0.0 => Some(movetime),
+            _ => return Err(format!("--movetime must be more than 0 and at most a week, not {}", seconds)),
+        },
+        None => None,
+    };
 
-    println!("{white_text:>width$}", white_text="WHITE PLAYER", width=3*width+width/2);
-    print!("{empty:>width$}", empty="", width=width);
-    for i in 0..AI_COUNT {
-        print!("{column:>width$}", column=players.keys().collect::<Vec<_>>()[i], width=width);
-    }
+    Ok(SearchLimits { depth: depth, movetime: movetime, .. SearchLimits::default() })
+}
 
-    println!("");
-    for j in 0..AI_COUNT {
-        print!("{row:>width$}", row=players.keys().collect::<Vec<_>>()[j], width=width);
-        let mut sum = 0f32;
-        for i in 0..AI_COUNT {
-            if i == j {
-                print!("{cell:>width$}", cell="-", width=width);
-            } else {
-                let cell = (results[i][j].0 - results[i][j].2) / rounds_per_match as f32;
-                sum += cell;
-                print!("{cell:>width$.2}", cell=cell, width=width);
-            }
-        }
+fn read_starting_state(arguments: &Arguments) -> Result<GameState, String> {
+    let fen = arguments.value("fen").unwrap_or(STARTING_FEN);
+    GameState::from_fen(fen).ok_or(format!("Invalid FEN: {}", fen))
+}
 
-        // Cumulative sum for the current black player.
-        println!("{cell:>width$.2}",
-                 cell=sum as f32/ (rounds_per_match as usize * (AI_COUNT - 1)) as f32,
-                 width=width);
-    }
+fn run_perft(arguments: &Arguments) -> Result<(), String> {
+    let game_state = read_starting_state(arguments)?;
+    let depth = match arguments.parse("depth")? {
+        Some(depth) if depth > 0 => depth,
+        _ => return Err("perft needs a --depth of at least 1".to_owned()),
+    };
 
-    println!("{empty:>width$}", empty="", width=width*(AI_COUNT+1));
-    print!("{text:>width$}", text="CUMULATIVE", width=width);
-    for i in 0..AI_COUNT {
-        let mut sum = 0f32;
-        for j in 0..AI_COUNT {
-            sum += results[i][j].0;
-            sum -= results[i][j].2;
-        }
+    println!("{}", perft::format_divide(&perft::divide(&game_state, depth)));
+    Ok(())
+}
 
-        print!("{cell:>width$.2}",
-               cell=sum as f32 / (rounds_per_match as usize * (AI_COUNT - 1)) as f32,
-               width=width);
+fn play_single_game(arguments: &Arguments) -> Result<(), String> {
+    let settings = read_game_settings(arguments)?;
+    let mut players = player::computer_players(settings.verbose);
+    players.register("human", Box::new(|| Box::new(HumanPlayer::new())));
+
+    let mut game_players = vec![
+        create_player(&players, arguments.value("white").unwrap_or("human"))?,
+        create_player(&players, arguments.value("black").unwrap_or(DEFAULT_OPPONENT))?,
+    ];
+    seed_players(&mut game_players, arguments.parse("seed")?);
+    let mut black = game_players.pop().unwrap();
+    let mut white = game_players.pop().unwrap();
+
+    let mut pgn_game = PgnGame::new(
+        "Single game", "1", white.name(), black.name(), &read_starting_state(arguments)?);
+    let result = tournament::play_game(&mut white, &mut black, &settings, &mut pgn_game);
+    if !settings.verbose {
+        println!("{}", result.pgn_format());
     }
 
-    println!("");
+    match arguments.value("pgn") {
+        Some(path) => pgn_game.append_to_file(path).map_err(|error| format!("Failed to write {}: {}", path, error)),
+        None => Ok(()),
+    }
 }
 
-fn play_game(
-        white: &Box<Fn(&GameState, &Vec<Move>, u8) -> Move>,
-        black: &Box<Fn(&GameState, &Vec<Move>, u8) -> Move>,
-        max_ai_depth: &u8) -> GameResult {
-
-    let mut game_state = GameState::opening_state();
-    let mut turn = 1;
-    let game_result;
-
-    loop {
-        println!("Turn {}", turn);
-        println!("{}", game_state.format());
-        match game_state.play_turn(white, max_ai_depth) {
-            PlayerState::Stalemate => {
-                game_result = GameResult::Draw; 
-                println!("Draw!");
-                break;
-            },
-            PlayerState::Checkmate => {
-                game_result = GameResult::BlackWon; 
-                println!("Black won!");
-                break;
-            },
-            _ => (),
-        };
-
-        println!("{}", game_state.format());
-        match game_state.play_turn(black, max_ai_depth) {
-            PlayerState::Stalemate => {
-                game_result = GameResult::Draw; 
-                println!("Draw!");
-                break; 
-            },
-            PlayerState::Checkmate => {
-                game_result = GameResult::WhiteWon; 
-                println!("White won!");
-                break;
-            },
-            _ => (),
-        }
+fn create_player(players: &PlayerRegistry, name: &str) -> Result<Box<dyn Player>, String> {
+    players.create(name).ok_or(format!("Unknown player: {}. Options: {:?}", name, players.names()))
+}
 
-        // TODO Replace this with the 50 moves rule.
-        if turn == 200 {
-            game_result = GameResult::Draw;
-            println!("Draw by hitting max moves!");
-            break;
+// Gives each player its own seed, so that the same seed plays the same games again.
+fn seed_players(players: &mut [Box<dyn Player>], seed: Option<u64>) {
+    if let Some(seed) = seed {
+        let mut rng = Random::new(seed);
+        for player in players.iter_mut() {
+            player.set_seed(rng.next_u64());
         }
+    }
+}
 
-        turn += 1;
+fn play_ai_round_robin(arguments: &Arguments) -> Result<(), String> {
+    let settings = read_game_settings(arguments)?;
+    let rounds_per_match = match arguments.parse("rounds")? {
+        Some(0) => return Err("--rounds must be at least 1".to_owned()),
+        Some(rounds) => rounds,
+        None => 1,
+    };
+
+    let registry = player::computer_players(settings.verbose);
+    let names = match arguments.value("players") {
+        Some(names) => names.split(',').map(|name| name.trim().to_owned()).collect::<Vec<_>>(),
+        None => registry.names().iter().map(|&name| name.to_owned()).collect(),
+    };
+    if names.len() < 2 {
+        return Err("A tournament needs at least two players".to_owned());
     }
 
-    println!("Game ended on turn {} .", turn);
-    game_result
-}
+    let mut players = names.iter()
+        .map(|name| create_player(&registry, name))
+        .collect::<Result<Vec<_>, _>>()?;
+    seed_players(&mut players, arguments.parse("seed")?);
+    let results = tournament::round_robin(
+        &mut players, rounds_per_match, &settings, "AI round robin", arguments.value("pgn"));
+
+    let report = TournamentReport::new(&names, &results);
+    let text = report.format_text();
+    print!("{}", text);
+    for &(option, ref text) in [
+            ("report-text", text),
+            ("report-csv", report.format_csv()),
+            ("report-json", report.format_json())].iter() {
+
+        if let Some(path) = arguments.value(option) {
+            File::create(path)
+                .and_then(|mut file| file.write_all(text.as_bytes()))
+                .map_err(|error| format!("Failed to write {}: {}", path, error))?;
+        }
+    }
 
-enum GameResult {
-    WhiteWon,
-    BlackWon,
-    Draw,
+    Ok(())
 }
@@ -0,0 +1,126 @@
+use game_state::GameState;
+use piece_move::Move;
+
+// Counts the leaf nodes of the legal move tree down to the specified depth. The command line prints
+// the divide counts instead, which include the total, so only the tests need this.
+#[cfg(test)]
+pub fn perft(game_state: &GameState, depth: u8) -> u64 {
+    count_leaves(&mut game_state.clone(), depth)
+}
+
+// Breaks down the perft count per root move so that a discrepancy against a reference engine
+// can be narrowed down to the offending move.
+pub fn divide(game_state: &GameState, depth: u8) -> Vec<(Move, u64)> {
+    if depth == 0 {
+        panic!("Zero depth specified!");
+    }
+
+    let mut next_game_state = game_state.clone();
+    game_state.get_legal_moves().into_iter()
+        .map(|piece_move| {
+            let undo = next_game_state.make_move(&piece_move);
+            let count = count_leaves(&mut next_game_state, depth - 1);
+            next_game_state.unmake_move(&piece_move, &undo);
+            (piece_move, count)
+        })
+        .collect()
+}
+
+// Plays out the tree on a single state, taking each move back once its subtree has been counted.
+fn count_leaves(game_state: &mut GameState, depth: u8) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let moves = game_state.get_legal_moves_in_place();
+
+    // The leaves don't need to be played out since only their count matters.
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+
+    let mut count = 0;
+    for piece_move in moves {
+        let undo = game_state.make_move(&piece_move);
+        count += count_leaves(game_state, depth - 1);
+        game_state.unmake_move(&piece_move, &undo);
+    }
+
+    count
+}
+
+pub fn format_divide(divide_counts: &[(Move, u64)]) -> String {
+    let mut lines = divide_counts.iter()
+        .map(|&(ref piece_move, count)| format!("{}: {}", piece_move.simple_format(), count))
+        .collect::<Vec<_>>();
+    lines.sort();
+
+    let total: u64 = divide_counts.iter().map(|&(_, count)| count).sum();
+    lines.push(String::new());
+    lines.push(format!("Nodes searched: {}", total));
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use game_state::STARTING_FEN;
+
+    // Reference counts from https://www.chessprogramming.org/Perft_Results
+    const KIWIPETE_FEN: &'static str =
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    const POSITION_3_FEN: &'static str = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1";
+    const POSITION_4_FEN: &'static str =
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+    const POSITION_5_FEN: &'static str =
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8";
+    const POSITION_6_FEN: &'static str =
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10";
+
+    fn assert_perft(fen: &str, expected_counts: &[u64]) {
+        let game_state = GameState::from_fen(fen).unwrap();
+        for (i, &expected_count) in expected_counts.iter().enumerate() {
+            let depth = i as u8 + 1;
+            assert_eq!(perft(&game_state, depth), expected_count, "{} at depth {}", fen, depth);
+        }
+    }
+
+    #[test]
+    fn initial_position() {
+        assert_perft(STARTING_FEN, &[20, 400, 8902]);
+    }
+
+    #[test]
+    fn kiwipete() {
+        assert_perft(KIWIPETE_FEN, &[48, 2039, 97862]);
+    }
+
+    #[test]
+    fn position_3() {
+        assert_perft(POSITION_3_FEN, &[14, 191, 2812]);
+    }
+
+    #[test]
+    fn position_4() {
+        assert_perft(POSITION_4_FEN, &[6, 264, 9467]);
+    }
+
+    #[test]
+    fn position_5() {
+        assert_perft(POSITION_5_FEN, &[44, 1486]);
+    }
+
+    #[test]
+    fn position_6() {
+        assert_perft(POSITION_6_FEN, &[46, 2079]);
+    }
+
+    #[test]
+    fn divide_sums_to_perft() {
+        let game_state = GameState::from_fen(KIWIPETE_FEN).unwrap();
+        let divide_counts = divide(&game_state, 2);
+        assert_eq!(divide_counts.len(), 48);
+        assert_eq!(divide_counts.iter().map(|&(_, count)| count).sum::<u64>(),
+                   perft(&game_state, 2));
+    }
+}
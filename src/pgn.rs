@@ -0,0 +1,439 @@
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use game_state::Color;
+use game_state::GameState;
+use game_state::STARTING_FEN;
+use piece_move::Move;
+
+// PGN export lines should be no longer than 80 characters.
+const MAX_LINE_LENGTH: usize = 80;
+
+// A game in Portable Game Notation: the tag pairs, and each played move alongside its SAN.
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub starting_state: GameState,
+    pub moves: Vec<(Move, String)>,
+}
+
+impl PgnGame {
+    // Starts a record with the Seven Tag Roster. The Result is unknown until set_result is called.
+    pub fn new(event: &str, round: &str, white: &str, black: &str, starting_state: &GameState) -> PgnGame {
+        let mut tags = vec![
+            ("Event".to_owned(), event.to_owned()),
+            ("Site".to_owned(), "?".to_owned()),
+            ("Date".to_owned(), current_date()),
+            ("Round".to_owned(), round.to_owned()),
+            ("White".to_owned(), white.to_owned()),
+            ("Black".to_owned(), black.to_owned()),
+            ("Result".to_owned(), "*".to_owned()),
+        ];
+
+        let fen = starting_state.format_fen();
+        if fen != STARTING_FEN {
+            tags.push(("SetUp".to_owned(), "1".to_owned()));
+            tags.push(("FEN".to_owned(), fen));
+        }
+
+        PgnGame {
+            tags: tags,
+            starting_state: starting_state.clone(),
+            moves: vec![],
+        }
+    }
+
+    pub fn get_tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter()
+            .find(|&&(ref tag_name, _)| tag_name == name)
+            .map(|&(_, ref value)| value.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter().position(|&(ref tag_name, _)| tag_name == name) {
+            Some(index) => self.tags[index].1 = value.to_owned(),
+            None => self.tags.push((name.to_owned(), value.to_owned())),
+        }
+    }
+
+    // Result should be one of "1-0", "0-1", "1/2-1/2" or "*".
+    pub fn set_result(&mut self, result: &str) {
+        self.set_tag("Result", result);
+    }
+
+    // Records a move played from the specified state, returning its SAN.
+    pub fn record_move(&mut self, game_state: &GameState, player_move: &Move) -> String {
+        let san = player_move.san_format(game_state);
+        self.moves.push((player_move.clone(), san.clone()));
+        san
+    }
+
+    pub fn format(&self) -> String {
+        let mut result = String::new();
+        for &(ref name, ref value) in &self.tags {
+            result.push_str(&format!("[{} \"{}\"]\n", name, escape_tag_value(value)));
+        }
+
+        result.push('\n');
+
+        let sans = self.moves.iter().map(|&(_, ref san)| san.clone()).collect::<Vec<_>>();
+        let mut tokens = movetext_tokens(&self.starting_state, sans);
+        tokens.push(self.get_tag("Result").unwrap_or("*").to_owned());
+
+        let mut line_length = 0;
+        for token in tokens {
+            if line_length > 0 && line_length + 1 + token.len() > MAX_LINE_LENGTH {
+                result.push('\n');
+                line_length = 0;
+            } else if line_length > 0 {
+                result.push(' ');
+                line_length += 1;
+            }
+
+            line_length += token.len();
+            result.push_str(&token);
+        }
+
+        result.push('\n');
+        result
+    }
+
+    // The state after the first ply_count moves have been played.
+    pub fn replay(&self, ply_count: usize) -> GameState {
+        let mut game_state = self.starting_state.clone();
+        for &(ref player_move, _) in self.moves.iter().take(ply_count) {
+            game_state.move_piece(player_move);
+        }
+
+        game_state
+    }
+
+    pub fn append_to_file(&self, path: &str) -> io::Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        // Games in a PGN database are separated by a blank line.
+        writeln!(file, "{}", self.format())
+    }
+}
+
+// Writes a line of legal moves from the specified state as numbered SAN, such as "12... Nf6 13. e5".
+pub fn format_line(game_state: &GameState, moves: &[Move]) -> String {
+    let mut next_game_state = game_state.clone();
+    let mut sans = vec![];
+    for player_move in moves {
+        sans.push(player_move.san_format(&next_game_state));
+        next_game_state.move_piece(player_move);
+    }
+
+    movetext_tokens(game_state, sans).join(" ")
+}
+
+// Numbers the moves, starting with "N..." if Black moves first.
+fn movetext_tokens(starting_state: &GameState, sans: Vec<String>) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut color = starting_state.current_player;
+    let mut move_number = starting_state.fullmove_number();
+    for (i, san) in sans.into_iter().enumerate() {
+        if color == Color::White {
+            tokens.push(format!("{}.", move_number));
+        } else if i == 0 {
+            tokens.push(format!("{}...", move_number));
+        }
+
+        tokens.push(san);
+        if color == Color::Black {
+            move_number += 1;
+        }
+
+        color = color.opposite();
+    }
+
+    tokens
+}
+
+// Parses every game in a PGN database, replaying the main line of each to validate its moves.
+// Comments, NAGs and variations are skipped. A game with an unparseable or illegal move is returned
+// as an error without affecting the games around it.
+pub fn parse_pgn(text: &str) -> Vec<Result<PgnGame, String>> {
+    let tokens = match tokenize(text) {
+        Ok(tokens) => tokens,
+        Err(message) => return vec![Err(message)],
+    };
+
+    let mut games = vec![];
+    let mut tags = vec![];
+    let mut sans = vec![];
+    let mut variation_depth = 0;
+    for token in tokens {
+        match token {
+            Token::Tag(name, value) => {
+                // A tag after movetext means that the previous game was missing its termination.
+                if !sans.is_empty() {
+                    games.push(build_game(tags, sans, None));
+                    tags = vec![];
+                    sans = vec![];
+                }
+
+                tags.push((name, value));
+            },
+            Token::VariationStart => variation_depth += 1,
+            Token::VariationEnd if variation_depth > 0 => variation_depth -= 1,
+            Token::VariationEnd => (),
+            Token::Symbol(_) if variation_depth > 0 => (),
+            Token::Symbol(ref symbol) if is_termination(symbol) => {
+                games.push(build_game(tags, sans, Some(symbol.as_str())));
+                tags = vec![];
+                sans = vec![];
+            },
+            // Move number indications.
+            Token::Symbol(ref symbol) if symbol.chars().all(|c| c.is_digit(10)) => (),
+            Token::Symbol(symbol) => sans.push(symbol),
+        }
+    }
+
+    if !tags.is_empty() || !sans.is_empty() {
+        games.push(build_game(tags, sans, None));
+    }
+
+    games
+}
+
+fn is_termination(symbol: &str) -> bool {
+    symbol == "1-0" || symbol == "0-1" || symbol == "1/2-1/2" || symbol == "*"
+}
+
+fn build_game(
+        tags: Vec<(String, String)>,
+        sans: Vec<String>,
+        termination: Option<&str>) -> Result<PgnGame, String> {
+
+    let starting_state = match tags.iter().find(|&&(ref name, _)| name == "FEN") {
+        None => GameState::opening_state(),
+        Some(&(_, ref fen)) => match GameState::from_fen(fen) {
+            Some(game_state) => game_state,
+            None => return Err(format!("Invalid FEN tag: {}", fen)),
+        },
+    };
+
+    let mut pgn_game = PgnGame {
+        tags: tags,
+        starting_state: starting_state.clone(),
+        moves: vec![],
+    };
+
+    if pgn_game.get_tag("Result").is_none() {
+        pgn_game.set_result(termination.unwrap_or("*"));
+    }
+
+    let mut game_state = starting_state;
+    for san in sans {
+        match Move::from_san(&san, &game_state) {
+            Some(player_move) => {
+                pgn_game.record_move(&game_state, &player_move);
+                game_state.move_piece(&player_move);
+            },
+            None => return Err(format!("Illegal or ambiguous move {} after {}",
+                san,
+                pgn_game.moves.iter().map(|&(_, ref san)| san.clone()).collect::<Vec<_>>().join(" "))),
+        }
+    }
+
+    Ok(pgn_game)
+}
+
+enum Token {
+    Tag(String, String),
+    Symbol(String),
+    VariationStart,
+    VariationEnd,
+}
+
+// Splits PGN text into the tokens that matter for replaying games. Comments, NAGs and periods are
+// dropped here, and move suffix annotations such as "!?" are stripped later along with checks.
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+    let mut at_line_start = true;
+    while let Some(c) = chars.next() {
+        let was_at_line_start = at_line_start;
+        at_line_start = c == '\n';
+        match c {
+            // Escaped lines and rest-of-line comments.
+            '%' if was_at_line_start => skip_line(&mut chars),
+            ';' => {
+                skip_line(&mut chars);
+                at_line_start = true;
+            },
+            '{' => {
+                if !chars.by_ref().any(|c| c == '}') {
+                    return Err("Unterminated comment.".to_owned());
+                }
+            },
+            '[' => tokens.push(read_tag(&mut chars)?),
+            '(' => tokens.push(Token::VariationStart),
+            ')' => tokens.push(Token::VariationEnd),
+            '$' => {
+                while chars.peek().map_or(false, |c| c.is_digit(10)) {
+                    chars.next();
+                }
+            },
+            _ if c.is_alphanumeric() || c == '*' => {
+                let mut symbol = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if next.is_alphanumeric() || "_+#=:-/!?".contains(next) {
+                        symbol.push(next);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+
+                tokens.push(Token::Symbol(symbol));
+            },
+            _ => (),
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn skip_line<I: Iterator<Item=char>>(chars: &mut I) {
+    for c in chars {
+        if c == '\n' {
+            break;
+        }
+    }
+}
+
+// Reads the rest of a tag pair such as [White "Kasparov, Garry"], after the opening bracket.
+fn read_tag<I: Iterator<Item=char>>(chars: &mut I) -> Result<Token, String> {
+    let mut name = String::new();
+    let mut value = String::new();
+    let mut in_value = false;
+    let mut is_escaped = false;
+    for c in chars {
+        if in_value {
+            match c {
+                _ if is_escaped => {
+                    value.push(c);
+                    is_escaped = false;
+                },
+                '\\' => is_escaped = true,
+                '"' => in_value = false,
+                _ => value.push(c),
+            }
+        } else {
+            match c {
+                '"' => in_value = true,
+                ']' => return Ok(Token::Tag(name, value)),
+                _ if c.is_whitespace() => (),
+                _ => name.push(c),
+            }
+        }
+    }
+
+    Err(format!("Unterminated tag pair: {}", name))
+}
+
+fn escape_tag_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// The current UTC date in the PGN YYYY.MM.DD format.
+fn current_date() -> String {
+    let days = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => (duration.as_secs() / 86400) as i64,
+        Err(_) => return "????.??.??".to_owned(),
+    };
+
+    // Converts days since 1970-01-01 to a civil date, treating years as starting in March so that
+    // the leap day is at the end of the year.
+    let shifted_days = days + 719468;
+    let era = shifted_days / 146097;
+    let day_of_era = shifted_days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAME: &'static str = r#"[Event "Test"]
+[Site "?"]
+[Date "2017.01.01"]
+[Round "1"]
+[White "White, A."]
+[Black "Black \"B\""]
+[Result "1-0"]
+
+1. e4 {The best by test} e5 2. Nf3 $1 (2. f4 exf4 (2... d5) 3. Nf3) 2... Nc6 ; Defending
+3. Bb5!? a6 4. Ba4 Nf6 5. O-O Be7 6. Bxc6 dxc6 7. Nxe5 Qd4 8. Nf3 Qxe4 9. Re1 Qf5
+10. Rxe7+ Kxe7 11. d4 1-0
+"#;
+
+    fn sans(pgn_game: &PgnGame) -> Vec<&str> {
+        pgn_game.moves.iter().map(|&(_, ref san)| san.as_str()).collect()
+    }
+
+    #[test]
+    fn parse_skips_comments_nags_and_variations() {
+        let games = parse_pgn(GAME);
+        assert_eq!(games.len(), 1);
+        let pgn_game = games[0].as_ref().unwrap();
+        assert_eq!(sans(pgn_game), vec![
+            "e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Ba4", "Nf6", "O-O", "Be7", "Bxc6", "dxc6",
+            "Nxe5", "Qd4", "Nf3", "Qxe4", "Re1", "Qf5", "Rxe7+", "Kxe7", "d4"]);
+        assert_eq!(pgn_game.get_tag("Black"), Some("Black \"B\""));
+        assert_eq!(pgn_game.get_tag("Result"), Some("1-0"));
+    }
+
+    #[test]
+    fn format_round_trip() {
+        let pgn_game = parse_pgn(GAME).pop().unwrap().unwrap();
+        let text = pgn_game.format();
+        assert_eq!(text, r#"[Event "Test"]
+[Site "?"]
+[Date "2017.01.01"]
+[Round "1"]
+[White "White, A."]
+[Black "Black \"B\""]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. Ba4 Nf6 5. O-O Be7 6. Bxc6 dxc6 7. Nxe5 Qd4 8.
+Nf3 Qxe4 9. Re1 Qf5 10. Rxe7+ Kxe7 11. d4 1-0
+"#);
+
+        let reparsed = parse_pgn(&text).pop().unwrap().unwrap();
+        assert_eq!(reparsed.tags, pgn_game.tags);
+        assert_eq!(reparsed.moves, pgn_game.moves);
+        assert_eq!(reparsed.format(), text);
+    }
+
+    #[test]
+    fn games_from_a_position() {
+        let text = "[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 40\"]\n\n40... Kd7 41. e4 *";
+        let pgn_game = parse_pgn(text).pop().unwrap().unwrap();
+        assert_eq!(sans(&pgn_game), vec!["Kd7", "e4"]);
+        assert_eq!(pgn_game.replay(2).format_fen(), "8/3k4/8/8/4P3/8/8/4K3 b - e3 0 41");
+        assert!(pgn_game.format().ends_with("\n40... Kd7 41. e4 *\n"));
+    }
+
+    // A game with a bad move is reported without losing the games around it.
+    #[test]
+    fn illegal_moves() {
+        let text = "1. e4 e5 1/2-1/2\n\n1. e4 e4 0-1\n\n1. d4 0-1";
+        let games = parse_pgn(text);
+        assert_eq!(games.len(), 3);
+        assert_eq!(games[0].as_ref().map(|pgn_game| pgn_game.get_tag("Result")), Ok(Some("1/2-1/2")));
+        assert!(games[1].is_err());
+        assert_eq!(games[2].as_ref().map(|pgn_game| sans(pgn_game)), Ok(vec!["d4"]));
+    }
+}
@@ -1,5 +1,6 @@
 use position::Position;
 use piece_type::PieceType;
+use game_state::GameState;
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Move {
@@ -80,8 +81,9 @@ impl Move {
         }
     }
 
+    // Parses long algebraic coordinates such as "e2e4", or "e7e8n" for a promotion.
     pub fn from_notation(notation: &str) -> Option<Move> {
-        if notation.len() != 4 {
+        if !notation.is_ascii() || (notation.len() != 4 && notation.len() != 5) {
             return None;
         }
 
@@ -91,10 +93,246 @@ impl Move {
             return None;
         }
 
-        Some (Move::simple(source.unwrap(), dest.unwrap()))
+        let promotion_piece_type = match notation[4..].to_lowercase().as_str() {
+            "" => None,
+            "n" => Some(PieceType::Knight),
+            "b" => Some(PieceType::Bishop),
+            "r" => Some(PieceType::Rook),
+            "q" => Some(PieceType::Queen),
+            _   => return None,
+        };
+
+        Some(Move {
+            promotion_piece_type: promotion_piece_type,
+            .. Move::simple(source.unwrap(), dest.unwrap())
+        })
+    }
+
+    // Finds the legal move in the specified state that the Standard Algebraic Notation refers to.
+    // Check, mate and annotation suffixes are ignored, as are a few common deviations such as
+    // castling with zeros, a promotion without the '=' or an explicit "e.p." after en passant.
+    pub fn from_san(san: &str, game_state: &GameState) -> Option<Move> {
+        let text = san.trim().trim_end_matches("e.p.").trim().trim_end_matches(|c| "+#!?".contains(c));
+        let legal_moves = game_state.get_legal_moves();
+
+        let castling_side = match text {
+            "O-O" | "0-0" => Some(true),
+            "O-O-O" | "0-0-0" => Some(false),
+            _ => None,
+        };
+        if let Some(is_kingside) = castling_side {
+            return legal_moves.into_iter()
+                .find(|m| m.extra_castling_move.is_some()
+                    && (m.destination.column > m.source.column) == is_kingside);
+        }
+
+        let mut chars = text.chars().collect::<Vec<_>>();
+        let piece_type = match chars.first() {
+            Some(&'N') => PieceType::Knight,
+            Some(&'B') => PieceType::Bishop,
+            Some(&'R') => PieceType::Rook,
+            Some(&'Q') => PieceType::Queen,
+            Some(&'K') => PieceType::King,
+            Some(&('a'..='h')) => PieceType::Pawn,
+            _ => return None,
+        };
+        if piece_type != PieceType::Pawn {
+            chars.remove(0);
+        }
+
+        let promotion_piece_type = match chars.last() {
+            Some(&'N') => Some(PieceType::Knight),
+            Some(&'B') => Some(PieceType::Bishop),
+            Some(&'R') => Some(PieceType::Rook),
+            Some(&'Q') => Some(PieceType::Queen),
+            _ => None,
+        };
+        if promotion_piece_type.is_some() {
+            chars.pop();
+            if chars.last() == Some(&'=') {
+                chars.pop();
+            }
+        }
+
+        if chars.len() < 2 {
+            return None;
+        }
+
+        let destination_text = chars.split_off(chars.len() - 2).into_iter().collect::<String>();
+        let destination = match Position::from_notation(&destination_text) {
+            Some(destination) => destination,
+            None => return None,
+        };
+
+        // Whatever remains is the capture marker and the disambiguating file and/or rank.
+        let mut source_column = None;
+        let mut source_row = None;
+        for c in chars {
+            match c {
+                'a'..='h' => source_column = Some(c as i8 - 'a' as i8),
+                '1'..='8' => source_row = Some(c as i8 - '1' as i8),
+                'x' | ':' => (),
+                _ => return None,
+            }
+        }
+
+        let mut candidates = legal_moves.into_iter()
+            .filter(|m| m.destination == destination
+                && m.promotion_piece_type == promotion_piece_type
+                && m.extra_castling_move.is_none()
+                && source_column.map_or(true, |column| m.source.column == column)
+                && source_row.map_or(true, |row| m.source.row == row)
+                && game_state.get_piece(&m.source).map_or(false, |piece| piece.piece_type == piece_type))
+            .collect::<Vec<_>>();
+
+        // Insufficiently disambiguated moves are rejected rather than guessed at.
+        if candidates.len() == 1 {
+            candidates.pop()
+        } else {
+            None
+        }
     }
 
     pub fn simple_format(&self) -> String {
-        format!("{}{}", self.source.format(), self.destination.format())
+        let promotion_text = self.promotion_piece_type
+            .map_or("".to_owned(), |piece_type| piece_type.letter().to_ascii_lowercase().to_string());
+
+        format!("{}{}{}", self.source.format(), self.destination.format(), promotion_text)
+    }
+
+    // Standard Algebraic Notation depends upon the position that the move is played from, which is
+    // needed to determine the moving piece, captures, disambiguation and check.
+    pub fn san_format(&self, game_state: &GameState) -> String {
+        let piece_type = game_state.get_piece(&self.source)
+            .expect("SAN can only be determined for moves of existing pieces.")
+            .piece_type;
+
+        let mut text = String::new();
+        if self.extra_castling_move.is_some() {
+            text.push_str(if self.destination.column > self.source.column { "O-O" } else { "O-O-O" });
+        } else {
+            let is_capture = game_state.get_piece(&self.destination).is_some()
+                || self.en_passant_target.is_some();
+            let source_text = self.source.format();
+            if piece_type == PieceType::Pawn {
+                if is_capture {
+                    text.push_str(&source_text[0..1]);
+                }
+            } else {
+                text.push(piece_type.letter());
+
+                // Only add the source file and/or rank if another piece of the same type could
+                // also have moved to the destination.
+                let rival_sources = game_state.get_legal_moves().into_iter()
+                    .filter(|other| other.destination == self.destination && other.source != self.source)
+                    .filter(|other| game_state.get_piece(&other.source)
+                        .map_or(false, |piece| piece.piece_type == piece_type))
+                    .map(|other| other.source)
+                    .collect::<Vec<_>>();
+                if !rival_sources.is_empty() {
+                    if rival_sources.iter().all(|source| source.column != self.source.column) {
+                        text.push_str(&source_text[0..1]);
+                    } else if rival_sources.iter().all(|source| source.row != self.source.row) {
+                        text.push_str(&source_text[1..2]);
+                    } else {
+                        text.push_str(&source_text);
+                    }
+                }
+            }
+
+            if is_capture {
+                text.push('x');
+            }
+
+            text.push_str(&self.destination.format());
+            if let Some(promotion_piece_type) = self.promotion_piece_type {
+                text.push('=');
+                text.push(promotion_piece_type.letter());
+            }
+        }
+
+        let mut next_game_state = game_state.clone();
+        next_game_state.move_piece(self);
+        if next_game_state.is_in_check(next_game_state.current_player) {
+            text.push(if next_game_state.get_legal_moves().is_empty() { '#' } else { '+' });
+        }
+
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use game_state::STARTING_FEN;
+
+    // The SAN is read as a legal move, and that move is written back as the same SAN.
+    fn assert_san(fen: &str, san: &str) {
+        let game_state = GameState::from_fen(fen).unwrap();
+        let player_move = Move::from_san(san, &game_state).expect(san);
+        assert_eq!(player_move.san_format(&game_state), san);
+    }
+
+    #[test]
+    fn piece_and_pawn_moves() {
+        assert_san(STARTING_FEN, "Nf3");
+        assert_san(STARTING_FEN, "e4");
+        assert_san("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2", "exd5");
+        assert_san("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3", "exf6");
+    }
+
+    #[test]
+    fn castling() {
+        assert_san("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "O-O");
+        assert_san("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", "O-O-O");
+    }
+
+    #[test]
+    fn promotion() {
+        assert_san("8/4P3/8/5k2/8/8/8/K7 w - - 0 1", "e8=Q");
+        assert_san("8/4P3/5k2/8/8/8/8/K7 w - - 0 1", "e8=N+");
+    }
+
+    #[test]
+    fn disambiguation() {
+        assert_san("rnbqkb1r/ppp1pppp/5n2/3p4/2PP4/8/PP2PPPP/RNBQKBNR b KQkq - 0 3", "Nbd7");
+        assert_san("rnbqkb1r/ppp1pppp/5n2/3p4/2PP4/8/PP2PPPP/RNBQKBNR b KQkq - 0 3", "Nfd7");
+        assert_san("4k3/R7/8/8/8/8/8/R3K3 w - - 0 1", "R1a3");
+        assert_san("4k3/8/8/8/8/8/Q6Q/4K2Q w - - 0 1", "Qh2g2");
+    }
+
+    #[test]
+    fn check_and_mate() {
+        assert_san("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2", "Bb5+");
+        assert_san("rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq g3 0 2", "Qh4#");
+    }
+
+    #[test]
+    fn lenient_san() {
+        let game_state = GameState::opening_state();
+        let nf3 = Move::from_san("Nf3", &game_state).unwrap();
+        assert_eq!(Move::from_san("Nf3!?", &game_state), Some(nf3.clone()));
+        assert_eq!(Move::from_san("Ng1f3", &game_state), Some(nf3));
+
+        let game_state = GameState::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        assert_eq!(Move::from_san("0-0", &game_state), Move::from_san("O-O", &game_state));
+
+        let game_state = GameState::from_fen("8/4P3/5k2/8/8/8/8/K7 w - - 0 1").unwrap();
+        assert_eq!(Move::from_san("e8N", &game_state), Move::from_san("e8=N+", &game_state));
+
+        let game_state = GameState::from_fen("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3").unwrap();
+        assert_eq!(Move::from_san("exf6 e.p.", &game_state), Move::from_san("exf6", &game_state));
+    }
+
+    #[test]
+    fn invalid_san() {
+        let game_state = GameState::from_fen("rnbqkb1r/ppp1pppp/5n2/3p4/2PP4/8/PP2PPPP/RNBQKBNR b KQkq - 0 3").unwrap();
+        // Ambiguous.
+        assert_eq!(Move::from_san("Nd7", &game_state), None);
+        // Illegal, or not a move at all.
+        assert_eq!(Move::from_san("Ke7", &game_state), None);
+        assert_eq!(Move::from_san("O-O", &game_state), None);
+        assert_eq!(Move::from_san("Nd9", &game_state), None);
+        assert_eq!(Move::from_san("", &game_state), None);
     }
 }
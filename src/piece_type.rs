@@ -1,4 +1,3 @@
-
 #[derive(Debug, PartialEq, Clone, Copy, Hash)]
 pub enum PieceType {
     Pawn,
@@ -9,3 +8,31 @@ pub enum PieceType {
     King
 }
 
+impl PieceType {
+    // The uppercase letter used for the piece in algebraic notation and FEN.
+    pub fn letter(&self) -> char {
+        match *self {
+            PieceType::Pawn   => 'P',
+            PieceType::Knight => 'N',
+            PieceType::Bishop => 'B',
+            PieceType::Rook   => 'R',
+            PieceType::Queen  => 'Q',
+            PieceType::King   => 'K',
+        }
+    }
+
+    // Used to index tables that have an entry for each piece type.
+    pub fn index(&self) -> usize {
+        match *self {
+            PieceType::Pawn   => 0,
+            PieceType::Knight => 1,
+            PieceType::Bishop => 2,
+            PieceType::Rook   => 3,
+            PieceType::Queen  => 4,
+            PieceType::King   => 5,
+        }
+    }
+}
+
+pub const ALL_PIECE_TYPES: [PieceType; 6] = [
+    PieceType::Pawn, PieceType::Knight, PieceType::Bishop, PieceType::Rook, PieceType::Queen, PieceType::King];
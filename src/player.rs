@@ -0,0 +1,83 @@
+use piece_move::Move;
+use game_state::GameState;
+use computer_player::SearchLimits;
+use ai_config;
+
+// Anything that can play a side in a game. Players are kept for a whole game, or a whole tournament,
+// so they can carry what they've learned from one move to the next.
+pub trait Player {
+    fn name(&self) -> &str;
+
+    // Picks one of the legal moves, which are never empty.
+    fn choose_move(&mut self, game_state: &GameState, moves: &[Move], limits: &SearchLimits) -> Move;
+
+    // Called before each game, including the first.
+    fn new_game(&mut self) {}
+
+    // Players that make random choices make the same ones again from the same seed.
+    fn set_seed(&mut self, _seed: u64) {}
+
+    // Asked once the player has chosen its move. A player that resigns doesn't have the move played.
+    fn resigns(&mut self, _game_state: &GameState) -> bool {
+        false
+    }
+
+    // Asked once the player's move has been played, as draws are offered along with a move.
+    fn offers_draw(&mut self, _game_state: &GameState) -> bool {
+        false
+    }
+
+    // Asked of the opponent of a player that offered a draw, before it's the opponent's turn.
+    fn accepts_draw(&mut self, _game_state: &GameState) -> bool {
+        false
+    }
+}
+
+// Creates players by name, in the order they were registered.
+pub struct PlayerRegistry {
+    factories: Vec<(String, Box<dyn Fn() -> Box<dyn Player>>)>,
+}
+
+impl PlayerRegistry {
+    pub fn new() -> PlayerRegistry {
+        PlayerRegistry { factories: vec![] }
+    }
+
+    // Registering a name again replaces the earlier player.
+    pub fn register(&mut self, name: &str, factory: Box<dyn Fn() -> Box<dyn Player>>) {
+        match self.factories.iter().position(|&(ref existing, _)| existing == name) {
+            Some(index) => self.factories[index].1 = factory,
+            None => self.factories.push((name.to_owned(), factory)),
+        }
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.factories.iter().map(|&(ref name, _)| name.as_str()).collect()
+    }
+
+    pub fn create(&self, name: &str) -> Option<Box<dyn Player>> {
+        self.factories.iter()
+            .find(|&&(ref existing, _)| existing == name)
+            .map(|&(_, ref factory)| factory())
+    }
+}
+
+// A registry of every AI declared in the config file, or of the built in AIs if there isn't one. Verbose
+// AIs print what their searches found.
+pub fn computer_players(verbose: bool) -> PlayerRegistry {
+    let definitions = ai_config::load_definitions(ai_config::AI_CONFIG_PATH).unwrap_or_else(|error| {
+        println!("{}. Using the built in AIs instead.", error);
+        ai_config::builtin_definitions()
+    });
+
+    let mut registry = PlayerRegistry::new();
+    for definition in definitions {
+        registry.register(&definition.name.clone(), Box::new(move || {
+            let mut player = definition.create_player();
+            player.set_verbose(verbose);
+            Box::new(player)
+        }));
+    }
+
+    registry
+}
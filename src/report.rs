@@ -0,0 +1,392 @@
+use tournament::PairingResult;
+
+// How many standard errors either side of an Elo estimate its error bars reach, for 95% confidence.
+const CONFIDENCE_Z: f64 = 1.96;
+
+// Games from one player's point of view.
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct Record {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl Record {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    // One point for a win and a half for a draw.
+    pub fn points(&self) -> f32 {
+        self.wins as f32 + self.draws as f32 / 2.0
+    }
+
+    fn add(&self, other: &Record) -> Record {
+        Record {
+            wins: self.wins + other.wins,
+            draws: self.draws + other.draws,
+            losses: self.losses + other.losses,
+        }
+    }
+
+    // Wins, draws and losses, such as "3-1-0".
+    fn format(&self) -> String {
+        format!("{}-{}-{}", self.wins, self.draws, self.losses)
+    }
+}
+
+// How one player did over the whole tournament.
+pub struct Standing {
+    pub name: String,
+    pub total: Record,
+    pub as_white: Record,
+    pub as_black: Record,
+    // Against each player, in the order the players were entered. The record against itself is empty.
+    pub against: Vec<Record>,
+    // The sum of the points scored against each opponent times that opponent's points. It breaks ties
+    // in favour of the player who did better against the stronger players.
+    pub sonneborn_berger: f32,
+    // The performance in Elo above the average of the player's opponents, and how far the error bars
+    // reach either side of it. Neither is known for a player who won or lost every game.
+    pub elo: Option<f64>,
+    pub elo_error: Option<f64>,
+}
+
+// The cross table and standings of a round robin.
+pub struct TournamentReport {
+    // In the order the players were entered.
+    standings: Vec<Standing>,
+    // Indexes of the standings from first place to last, by points and then by Sonneborn-Berger.
+    ranking: Vec<usize>,
+}
+
+impl TournamentReport {
+    // The results are indexed by the White player, then the Black player, as round_robin returns them.
+    pub fn new(names: &[String], results: &[Vec<PairingResult>]) -> TournamentReport {
+        let mut standings = names.iter().enumerate().map(|(i, name)| {
+            let mut as_white = Record::default();
+            let mut as_black = Record::default();
+            let mut against = vec![Record::default(); names.len()];
+            for j in 0..names.len() {
+                if i == j {
+                    continue;
+                }
+
+                let (white_games, black_games) = (results[i][j], results[j][i]);
+                let white_record = Record {
+                    wins: white_games.white_wins,
+                    draws: white_games.draws,
+                    losses: white_games.black_wins,
+                };
+                let black_record = Record {
+                    wins: black_games.black_wins,
+                    draws: black_games.draws,
+                    losses: black_games.white_wins,
+                };
+                as_white = as_white.add(&white_record);
+                as_black = as_black.add(&black_record);
+                against[j] = white_record.add(&black_record);
+            }
+
+            let total = as_white.add(&as_black);
+            let (elo, elo_error) = elo_estimate(&total);
+            Standing {
+                name: name.clone(),
+                total: total,
+                as_white: as_white,
+                as_black: as_black,
+                against: against,
+                sonneborn_berger: 0.0,
+                elo: elo,
+                elo_error: elo_error,
+            }
+        }).collect::<Vec<_>>();
+
+        let points = standings.iter().map(|standing| standing.total.points()).collect::<Vec<_>>();
+        for standing in standings.iter_mut() {
+            standing.sonneborn_berger = standing.against.iter().zip(points.iter())
+                .map(|(record, &opponent_points)| record.points() * opponent_points)
+                .sum();
+        }
+
+        let mut ranking = (0..standings.len()).collect::<Vec<_>>();
+        ranking.sort_by(|&a, &b| {
+            let key = |i: usize| (standings[i].total.points(), standings[i].sonneborn_berger);
+            key(b).partial_cmp(&key(a)).unwrap()
+        });
+
+        TournamentReport { standings: standings, ranking: ranking }
+    }
+
+    // The standings from first place to last.
+    pub fn ranked_standings(&self) -> Vec<&Standing> {
+        self.ranking.iter().map(|&i| &self.standings[i]).collect()
+    }
+
+    pub fn format_text(&self) -> String {
+        let name_width = self.standings.iter()
+            .map(|standing| standing.name.chars().count())
+            .max().unwrap_or(0).max(6);
+        let cell_width = self.standings.iter()
+            .flat_map(|standing| standing.against.iter().map(|record| record.format().len()))
+            .max().unwrap_or(0).max(5) + 2;
+
+        let mut text = String::new();
+        text.push_str("Cross table of each row's wins-draws-losses against each column:\n");
+        text.push_str(&format!("{:>4} {:<width$}", "", "", width=name_width));
+        for rank in 1..self.ranking.len() + 1 {
+            text.push_str(&format!("{:>width$}", rank, width=cell_width));
+        }
+
+        text.push('\n');
+        for (rank, &i) in self.ranking.iter().enumerate() {
+            text.push_str(&format!("{:>4} {:<width$}", rank + 1, self.standings[i].name, width=name_width));
+            for &j in self.ranking.iter() {
+                let cell = if i == j { "-".to_owned() } else { self.standings[i].against[j].format() };
+                text.push_str(&format!("{:>width$}", cell, width=cell_width));
+            }
+
+            text.push('\n');
+        }
+
+        text.push_str("\nStandings:\n");
+        text.push_str(&format!("{:>4} {:<width$} {:>5} {:>6} {:>6} {:>9} {:>9} {:>9} {:>7} {:>12}\n",
+            "Rank", "Player", "Games", "Points", "Score", "W-D-L", "As White", "As Black", "S-B", "Elo",
+            width=name_width));
+        for (rank, standing) in self.ranked_standings().into_iter().enumerate() {
+            let elo = match (standing.elo, standing.elo_error) {
+                (Some(elo), Some(error)) => format!("{:+.0} ± {:.0}", elo, error),
+                (Some(elo), None) => format!("{:+.0}", elo),
+                _ => "-".to_owned(),
+            };
+            text.push_str(&format!("{:>4} {:<width$} {:>5} {:>6.1} {:>5.1}% {:>9} {:>9} {:>9} {:>7.2} {:>12}\n",
+                rank + 1,
+                standing.name,
+                standing.total.games(),
+                standing.total.points(),
+                score_fraction(&standing.total) * 100.0,
+                standing.total.format(),
+                standing.as_white.format(),
+                standing.as_black.format(),
+                standing.sonneborn_berger,
+                elo,
+                width=name_width));
+        }
+
+        // Each game is counted once, from White's point of view.
+        let games = self.standings.iter()
+            .fold(Record::default(), |games, standing| games.add(&standing.as_white));
+        if games.games() > 0 {
+            let percent = |count: u32| count as f32 * 100.0 / games.games() as f32;
+            text.push_str(&format!("\n{} games: White won {:.1}%, Black won {:.1}% and {:.1}% were drawn.\n",
+                games.games(), percent(games.wins), percent(games.losses), percent(games.draws)));
+        }
+
+        text
+    }
+
+    // One row per player from first place to last, ending with a column of wins-draws-losses against
+    // each player in the same order.
+    pub fn format_csv(&self) -> String {
+        let mut header = vec![
+            "rank", "player", "games", "points", "score", "wins", "draws", "losses",
+            "white_wins", "white_draws", "white_losses", "black_wins", "black_draws", "black_losses",
+            "sonneborn_berger", "elo", "elo_error",
+        ].into_iter().map(|column| column.to_owned()).collect::<Vec<_>>();
+        for standing in self.ranked_standings() {
+            header.push(csv_field(&format!("vs {}", standing.name)));
+        }
+
+        let mut lines = vec![header.join(",")];
+        for (rank, &i) in self.ranking.iter().enumerate() {
+            let standing = &self.standings[i];
+            let mut row = vec![
+                (rank + 1).to_string(),
+                csv_field(&standing.name),
+                standing.total.games().to_string(),
+                standing.total.points().to_string(),
+                format!("{:.4}", score_fraction(&standing.total)),
+            ];
+            for record in [standing.total, standing.as_white, standing.as_black].iter() {
+                row.push(record.wins.to_string());
+                row.push(record.draws.to_string());
+                row.push(record.losses.to_string());
+            }
+
+            row.push(standing.sonneborn_berger.to_string());
+            row.push(standing.elo.map_or("".to_owned(), |elo| format!("{:.1}", elo)));
+            row.push(standing.elo_error.map_or("".to_owned(), |error| format!("{:.1}", error)));
+            for &j in self.ranking.iter() {
+                row.push(if i == j { "".to_owned() } else { standing.against[j].format() });
+            }
+
+            lines.push(row.join(","));
+        }
+
+        lines.join("\n") + "\n"
+    }
+
+    // The standings from first place to last, with each player's records against the others.
+    pub fn format_json(&self) -> String {
+        let record_json = |record: &Record| format!(
+            "{{\"wins\": {}, \"draws\": {}, \"losses\": {}}}", record.wins, record.draws, record.losses);
+        let number_json = |number: Option<f64>| number.map_or("null".to_owned(), |number| format!("{:.1}", number));
+
+        let players = self.ranking.iter().enumerate().map(|(rank, &i)| {
+            let standing = &self.standings[i];
+            let against = self.ranking.iter()
+                .filter(|&&j| j != i)
+                .map(|&j| format!("        {{\"opponent\": {}, \"record\": {}}}",
+                    json_string(&self.standings[j].name), record_json(&standing.against[j])))
+                .collect::<Vec<_>>();
+            format!("    {{\n      \"rank\": {},\n      \"player\": {},\n      \"points\": {},\n      \
+                \"record\": {},\n      \"as_white\": {},\n      \"as_black\": {},\n      \
+                \"sonneborn_berger\": {},\n      \"elo\": {},\n      \"elo_error\": {},\n      \
+                \"against\": [\n{}\n      ]\n    }}",
+                rank + 1,
+                json_string(&standing.name),
+                standing.total.points(),
+                record_json(&standing.total),
+                record_json(&standing.as_white),
+                record_json(&standing.as_black),
+                standing.sonneborn_berger,
+                number_json(standing.elo),
+                number_json(standing.elo_error),
+                against.join(",\n"))
+        }).collect::<Vec<_>>();
+
+        format!("{{\n  \"standings\": [\n{}\n  ]\n}}\n", players.join(",\n"))
+    }
+}
+
+fn score_fraction(record: &Record) -> f32 {
+    if record.games() == 0 { 0.0 } else { record.points() / record.games() as f32 }
+}
+
+// The Elo difference at which this fraction of the points is expected.
+fn elo_difference(score: f64) -> Option<f64> {
+    if score <= 0.0 || score >= 1.0 {
+        None
+    } else {
+        Some(400.0 * (score / (1.0 - score)).log10())
+    }
+}
+
+// The performance rating of a record against opponents of the same average strength, and half the
+// width of its confidence interval. The interval comes from the spread of the results of each game.
+fn elo_estimate(record: &Record) -> (Option<f64>, Option<f64>) {
+    let games = record.games() as f64;
+    if games == 0.0 {
+        return (None, None);
+    }
+
+    let score = record.points() as f64 / games;
+    let variance = (record.wins as f64 * (1.0 - score).powi(2)
+        + record.draws as f64 * (0.5 - score).powi(2)
+        + record.losses as f64 * score.powi(2)) / games;
+    let margin = CONFIDENCE_Z * (variance / games).sqrt();
+    let error = match (elo_difference(score - margin), elo_difference(score + margin)) {
+        (Some(low), Some(high)) => Some((high - low) / 2.0),
+        _ => None,
+    };
+
+    (elo_difference(score), error)
+}
+
+// Quotes fields that contain commas, quotes or line breaks.
+fn csv_field(text: &str) -> String {
+    if text.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_owned()
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut result = "\"".to_owned();
+    for c in text.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+
+    result.push('"');
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(wins: u32, draws: u32, losses: u32) -> Record {
+        Record { wins: wins, draws: draws, losses: losses }
+    }
+
+    #[test]
+    fn elo_estimates() {
+        let (elo, error) = elo_estimate(&record(30, 0, 10));
+        assert_eq!(elo.map(|elo| elo.round()), Some(191.0));
+        assert!(error.map_or(false, |error| error > 0.0));
+        // The same score over fewer games can't rule out winning every game.
+        let (elo, error) = elo_estimate(&record(3, 0, 1));
+        assert_eq!(elo.map(|elo| elo.round()), Some(191.0));
+        assert_eq!(error, None);
+        assert_eq!(elo_estimate(&record(1, 2, 1)).0, Some(0.0));
+        assert_eq!(elo_estimate(&record(0, 0, 4)), (None, None));
+        assert_eq!(elo_estimate(&record(4, 0, 0)), (None, None));
+        assert_eq!(elo_estimate(&record(0, 0, 0)), (None, None));
+    }
+
+    // A and B tie on points, but A beat S, who did best, while B only beat W, who did worst.
+    #[test]
+    fn sonneborn_berger_breaks_ties() {
+        let names = ["B", "A", "W", "S"].iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        let game = |white_wins, draws, black_wins| PairingResult {
+            white_wins: white_wins, black_wins: black_wins, draws: draws,
+        };
+        let mut results = vec![vec![PairingResult::default(); 4]; 4];
+        let (b, a, w, s) = (0, 1, 2, 3);
+        results[a][s] = game(1, 0, 0);
+        results[a][w] = game(0, 0, 1);
+        results[a][b] = game(0, 1, 0);
+        results[b][s] = game(0, 0, 1);
+        results[b][w] = game(1, 0, 0);
+        results[s][w] = game(1, 0, 0);
+
+        let report = TournamentReport::new(&names, &results);
+        let standings = report.ranked_standings();
+        assert_eq!(standings.iter().map(|standing| standing.name.as_str()).collect::<Vec<_>>(),
+            vec!["S", "A", "B", "W"]);
+        assert_eq!(standings.iter().map(|standing| standing.total.points()).collect::<Vec<_>>(),
+            vec![2.0, 1.5, 1.5, 1.0]);
+        assert_eq!(standings[1].sonneborn_berger, 2.75);
+        assert_eq!(standings[2].sonneborn_berger, 1.75);
+        assert_eq!(standings[1].as_white, record(1, 1, 1));
+        assert_eq!(standings[2].as_white, record(1, 0, 1));
+        assert_eq!(standings[2].as_black, record(0, 1, 0));
+    }
+
+    #[test]
+    fn csv_escaping() {
+        assert_eq!(csv_field("spaces_moves"), "spaces_moves");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn json_escaping() {
+        assert_eq!(json_string("spaces_moves"), "\"spaces_moves\"");
+        assert_eq!(json_string("say \"hi\""), "\"say \\\"hi\\\"\"");
+        assert_eq!(json_string("back\\slash"), "\"back\\\\slash\"");
+        assert_eq!(json_string("tab\tand\nline"), "\"tab\\tand\\nline\"");
+        assert_eq!(json_string("bell\u{7}"), "\"bell\\u0007\"");
+        assert_eq!(json_string("café"), "\"café\"");
+    }
+}
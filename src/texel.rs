@@ -0,0 +1,271 @@
+use std::fs::File;
+use std::io::Read;
+
+use ai_config;
+use cli::Arguments;
+use computer_player;
+use computer_player::PieceValues;
+use computer_player::Weights;
+use game_state::GameState;
+use tuning;
+
+// The parameters are the piece values from the pawn up to the queen, then the space and move weights,
+// in the same order as computer_player::evaluation_terms. The piece weight is left at one, since
+// only its product with the piece values matters.
+type Parameters = [i32; 7];
+
+// The biggest change tried to each parameter. It's halved whenever no change helps, down to one.
+const INITIAL_STEP: i32 = 16;
+
+const DEFAULT_STARTING_PLAYER: &'static str = "spaces_moves";
+
+// A position with the result of the game it came from, from White's perspective: 1 for a White win,
+// 0.5 for a draw and 0 for a Black win.
+struct LabelledPosition {
+    terms: [i16; 7],
+    result: f64,
+}
+
+// Fits the evaluation to positions labelled with the results of their games, by finding the
+// parameters whose evaluations best predict the results. This is Texel's tuning method. The
+// positions are evaluated without searching, so they should be quiet ones.
+pub fn run_texel_tuning(arguments: &Arguments) -> Result<(), String> {
+    let path = arguments.value("positions").ok_or("texel needs --positions".to_owned())?;
+    let name = arguments.value("start").unwrap_or(DEFAULT_STARTING_PLAYER);
+    let start = starting_parameters(name).ok_or(format!("Unknown AI: {}", name))?;
+    if start.iter().any(|&parameter| parameter < 0)
+            || computer_player::max_evaluation(&start) > computer_player::MAX_EVALUATION {
+        return Err(format!("{}'s evaluations could reach the checkmate scores, so it can't be tuned", name));
+    }
+
+    let positions = load_positions(path)?;
+
+    let scale = fit_scale(&positions, &start);
+    println!("Scale: {:.4}", scale);
+    println!("Error before: {:.6}", error(&positions, &start, scale));
+    let parameters = local_search(&positions, start, scale);
+    println!("Error after: {:.6}", error(&positions, &parameters, scale));
+
+    let (weights, piece_values) = to_weights(&parameters);
+    println!("Weights: {}, piece values: {:?}", tuning::format_weights(&weights), piece_values);
+    tuning::save_as_ai(arguments.value("save-as"), weights, piece_values)
+}
+
+fn load_positions(path: &str) -> Result<Vec<LabelledPosition>, String> {
+    let mut text = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut text))
+        .map_err(|error| format!("Failed to read {}: {}", path, error))?;
+
+    let mut positions = vec![];
+    let mut skipped = 0;
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        match parse_labelled_position(line) {
+            Some((game_state, result)) => positions.push(LabelledPosition {
+                terms: computer_player::evaluation_terms(&game_state),
+                result: result,
+            }),
+            None => skipped += 1,
+        }
+    }
+
+    println!("Loaded {} positions, skipping {} lines that couldn't be read", positions.len(), skipped);
+    if positions.is_empty() {
+        return Err(format!("No labelled positions in {}", path));
+    }
+
+    Ok(positions)
+}
+
+// Reads a FEN, with or without its move counters, followed by the result. Results can be quoted and
+// preceded by an EPD opcode, as in 'c9 "1/2-1/2";', or be the score for White, such as 0.5.
+fn parse_labelled_position(line: &str) -> Option<(GameState, f64)> {
+    let tokens = line.split(|c: char| c.is_whitespace() || c == '"' || c == ';')
+        .filter(|token| !token.is_empty())
+        .collect::<Vec<_>>();
+    let result = tokens.iter().rev().filter_map(|token| parse_result(token)).next()?;
+    let has_counters = tokens.len() >= 6 && tokens[4..6].iter().all(|token| token.parse::<u16>().is_ok());
+    let field_count = if has_counters { 6 } else { 4 };
+    if tokens.len() <= field_count {
+        return None;
+    }
+
+    GameState::from_fen(&tokens[..field_count].join(" ")).map(|game_state| (game_state, result))
+}
+
+fn parse_result(token: &str) -> Option<f64> {
+    match token {
+        "1-0" => Some(1.0),
+        "0-1" => Some(0.0),
+        "1/2-1/2" => Some(0.5),
+        _ => token.parse().ok().filter(|&result| result >= 0.0 && result <= 1.0),
+    }
+}
+
+// The parameters of an AI from the config file, or of a built in AI.
+fn starting_parameters(name: &str) -> Option<Parameters> {
+    let definition = ai_config::load_definitions(ai_config::AI_CONFIG_PATH).unwrap_or(vec![]).into_iter()
+        .chain(ai_config::builtin_definitions())
+        .find(|definition| definition.name == name)?;
+    let (weights, piece_values) = (definition.weights, definition.piece_values);
+
+    let mut parameters = [0; 7];
+    for (parameter, &value) in parameters.iter_mut().zip(piece_values.iter()) {
+        *parameter = weights[0] as i32 * value as i32;
+    }
+
+    parameters[5] = weights[1] as i32;
+    parameters[6] = weights[2] as i32;
+    Some(parameters)
+}
+
+fn to_weights(parameters: &Parameters) -> (Weights, PieceValues) {
+    let mut piece_values = [0; 5];
+    for (value, &parameter) in piece_values.iter_mut().zip(parameters.iter()) {
+        *value = parameter as i16;
+    }
+
+    ([1, parameters[5] as i16, parameters[6] as i16], piece_values)
+}
+
+// The evaluation of a position, from White's perspective.
+fn evaluate(parameters: &Parameters, position: &LabelledPosition) -> f64 {
+    parameters.iter().zip(position.terms.iter())
+        .map(|(&parameter, &term)| parameter * term as i32)
+        .sum::<i32>() as f64
+}
+
+// The mean squared difference between the results and the results predicted from the evaluations.
+// The scale sets how sure an evaluation is of the result: at an evaluation of 400 / scale, a win is
+// predicted to be ten times as likely as a loss.
+fn error(positions: &[LabelledPosition], parameters: &Parameters, scale: f64) -> f64 {
+    positions.iter().map(|position| {
+        let predicted = 1.0 / (1.0 + 10f64.powf(-scale * evaluate(parameters, position) / 400.0));
+        (position.result - predicted).powi(2)
+    }).sum::<f64>() / positions.len() as f64
+}
+
+// The scale that best fits the starting parameters, which is then kept for the whole tuning.
+fn fit_scale(positions: &[LabelledPosition], parameters: &Parameters) -> f64 {
+    let mut scale = 1.0;
+    let mut best_error = error(positions, parameters, scale);
+    for &step in [1.0, 0.1, 0.01, 0.001].iter() {
+        loop {
+            let candidates = [scale + step, scale - step];
+            let better = candidates.iter()
+                .filter(|&&candidate| candidate > 0.0)
+                .map(|&candidate| (candidate, error(positions, parameters, candidate)))
+                .filter(|&(_, candidate_error)| candidate_error < best_error)
+                .next();
+            match better {
+                Some((candidate, candidate_error)) => {
+                    scale = candidate;
+                    best_error = candidate_error;
+                },
+                None => break,
+            }
+        }
+    }
+
+    scale
+}
+
+// Tries moving each parameter up and down by the step, keeping any change that lowers the error,
+// until none do at the smallest step. Changes that would let evaluations reach the checkmate scores
+// aren't tried, so the parameters have to start clear of them.
+fn local_search(positions: &[LabelledPosition], mut parameters: Parameters, scale: f64) -> Parameters {
+    let mut best_error = error(positions, &parameters, scale);
+    let mut step = INITIAL_STEP;
+    while step > 0 {
+        let mut improved = false;
+        for i in 0..parameters.len() {
+            // A pawn has to be worth something for the quiescence search's pruning.
+            let min = if i == 0 { 1 } else { 0 };
+            for &change in [step, -step].iter() {
+                let mut candidate = parameters;
+                candidate[i] = (candidate[i] + change).max(min);
+                if candidate[i] == parameters[i]
+                        || computer_player::max_evaluation(&candidate) > computer_player::MAX_EVALUATION {
+                    continue;
+                }
+
+                let candidate_error = error(positions, &candidate, scale);
+                if candidate_error < best_error {
+                    parameters = candidate;
+                    best_error = candidate_error;
+                    improved = true;
+                    break;
+                }
+            }
+        }
+
+        println!("Step {}: error {:.6}", step, best_error);
+        if !improved {
+            step /= 2;
+        }
+    }
+
+    parameters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use game_state::STARTING_FEN;
+
+    // Positions that differ only by some number of pawns, labelled with the result that the evaluation
+    // of a pawn as pawn_value predicts at the given scale.
+    fn pawn_positions(pawn_value: i32, scale: f64) -> Vec<LabelledPosition> {
+        (-3..4).map(|pawns| {
+            let terms = [pawns, 0, 0, 0, 0, 0, 0];
+            let evaluation = (pawn_value * pawns as i32) as f64;
+            LabelledPosition { terms: terms, result: 1.0 / (1.0 + 10f64.powf(-scale * evaluation / 400.0)) }
+        }).collect()
+    }
+
+    #[test]
+    fn labelled_positions() {
+        let (_, result) = parse_labelled_position(&format!("{} 1-0", STARTING_FEN)).unwrap();
+        assert_eq!(result, 1.0);
+        let (game_state, result) = parse_labelled_position(
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 c9 \"1/2-1/2\";").unwrap();
+        assert_eq!(result, 0.5);
+        assert_eq!(game_state.format_fen(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+        let (_, result) = parse_labelled_position(&format!("{} 0.25", STARTING_FEN)).unwrap();
+        assert_eq!(result, 0.25);
+        let (_, result) = parse_labelled_position("8/8/8/8/8/k7/8/K7 w - - 0-1").unwrap();
+        assert_eq!(result, 0.0);
+
+        assert!(parse_labelled_position(STARTING_FEN).is_none());
+        assert!(parse_labelled_position("8/8/8 w - - 1-0").is_none());
+    }
+
+    #[test]
+    fn fits_the_scale() {
+        let positions = pawn_positions(100, 1.5);
+        let scale = fit_scale(&positions, &[100, 0, 0, 0, 0, 0, 0]);
+        assert!((scale - 1.5).abs() < 0.01, "{}", scale);
+    }
+
+    #[test]
+    fn fits_the_parameters() {
+        let positions = pawn_positions(100, 1.0);
+        let parameters = local_search(&positions, [60, 200, 200, 300, 500, 5, 1], 1.0);
+        assert!((parameters[0] - 100).abs() <= 1, "{:?}", parameters);
+        // Nothing else affects the evaluations of these positions, so nothing else changes.
+        assert_eq!(&parameters[1..], &[200, 200, 300, 500, 5, 1]);
+    }
+
+    // Even when the results call for ever larger parameters, evaluations mustn't reach the checkmate
+    // scores.
+    #[test]
+    fn parameters_stay_clear_of_mate_scores() {
+        let positions = (1..4).map(|queens| {
+            LabelledPosition { terms: [0, 0, 0, 0, queens, 0, 0], result: 1.0 }
+        }).collect::<Vec<_>>();
+        let start = [100, 300, 300, 500, 600, 7, 1];
+        let parameters = local_search(&positions, start, 1.0);
+        assert!(parameters[4] > start[4]);
+        assert!(computer_player::max_evaluation(&parameters) <= computer_player::MAX_EVALUATION);
+    }
+}
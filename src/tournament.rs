@@ -0,0 +1,203 @@
+use game_state::Color;
+use game_state::GameState;
+use game_state::MoveLimitRule;
+use game_state::PlayerState;
+use computer_player::SearchLimits;
+use player::Player;
+use pgn::PgnGame;
+use clock::Clock;
+use clock::TimeControl;
+
+// How the games in a match or tournament are played.
+pub struct GameSettings {
+    pub time_control: Option<TimeControl>,
+    // Used for untimed games only, since the clocks set the limits in timed games.
+    pub limits: SearchLimits,
+    pub move_limit_rule: MoveLimitRule,
+    // Whether to print the board before every move, the moves and the finished game.
+    pub verbose: bool,
+}
+
+// The games that one player had White in against another.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct PairingResult {
+    pub white_wins: u32,
+    pub black_wins: u32,
+    pub draws: u32,
+}
+
+// Plays every player against every other player, once as White and once as Black in every round. The
+// results are indexed by the White player, then the Black player. Each game is appended to the PGN
+// file if there is one.
+pub fn round_robin(
+        players: &mut [Box<dyn Player>],
+        rounds: u8,
+        settings: &GameSettings,
+        event: &str,
+        pgn_path: Option<&str>) -> Vec<Vec<PairingResult>> {
+
+    let player_count = players.len();
+    let mut results = vec![vec![PairingResult::default(); player_count]; player_count];
+    for round in 0..rounds {
+        for i in 0..player_count {
+            for j in 0..player_count {
+                if i == j {
+                    continue;
+                }
+
+                let (white, black) = pair_mut(players, i, j);
+                let mut pgn_game = PgnGame::new(
+                    event, &(round + 1).to_string(), white.name(), black.name(), &GameState::opening_state());
+                match play_game(white, black, settings, &mut pgn_game) {
+                    GameResult::WhiteWon => results[i][j].white_wins += 1,
+                    GameResult::BlackWon => results[i][j].black_wins += 1,
+                    GameResult::Draw     => results[i][j].draws += 1,
+                };
+
+                if let Some(path) = pgn_path {
+                    if let Err(error) = pgn_game.append_to_file(path) {
+                        println!("Failed to write the game to {}: {}", path, error);
+                    }
+                }
+            }
+        }
+    }
+
+    results
+}
+
+// Borrows two different players at once.
+fn pair_mut<T>(items: &mut [T], i: usize, j: usize) -> (&mut T, &mut T) {
+    if i < j {
+        let (left, right) = items.split_at_mut(j);
+        (&mut left[i], &mut right[0])
+    } else {
+        let (left, right) = items.split_at_mut(i);
+        (&mut right[0], &mut left[j])
+    }
+}
+
+// Plays a game out from the PGN record's starting position, recording the moves and the result. In
+// timed games, the players' limits come from their clocks instead.
+pub fn play_game(
+        white: &mut Box<dyn Player>,
+        black: &mut Box<dyn Player>,
+        settings: &GameSettings,
+        pgn_game: &mut PgnGame) -> GameResult {
+
+    let log = |text: String| if settings.verbose { println!("{}", text) };
+    let mut game_state = pgn_game.starting_state.clone();
+    game_state.set_move_limit_rule(settings.move_limit_rule);
+    let mut clocks = settings.time_control
+        .map(|time_control| [Clock::new(time_control), Clock::new(time_control)]);
+    if let Some(time_control) = settings.time_control {
+        pgn_game.set_tag("TimeControl", &time_control.format());
+    }
+
+    white.new_game();
+    black.new_game();
+    let game_result;
+    loop {
+        let current_player = game_state.current_player;
+        let (player, opponent, opponent_won) = match current_player {
+            Color::White => (&mut *white, &mut *black, GameResult::BlackWon),
+            Color::Black => (&mut *black, &mut *white, GameResult::WhiteWon),
+        };
+
+        if current_player == Color::White {
+            log(format!("Turn {}", game_state.fullmove_number()));
+        }
+
+        log(game_state.format());
+        let moves = match game_state.get_player_moves() {
+            PlayerState::CanMove(moves) => moves,
+            PlayerState::Stalemate => {
+                game_result = GameResult::Draw;
+                log("Draw!".to_owned());
+                break;
+            },
+            PlayerState::Checkmate => {
+                game_result = opponent_won;
+                log(format!("{:?} won!", current_player.opposite()));
+                break;
+            },
+            PlayerState::Draw(draw_reason) => {
+                game_result = GameResult::Draw;
+                log(format!("Draw by {:?}!", draw_reason));
+                break;
+            },
+        };
+
+        let turn_limits = match clocks {
+            Some(ref mut clocks) => {
+                let clock = &mut clocks[current_player.index()];
+                clock.start();
+                clock.search_limits()
+            },
+            None => settings.limits.clone(),
+        };
+
+        let player_move = player.choose_move(&game_state, &moves, &turn_limits);
+        if let Some(ref mut clocks) = clocks {
+            if !clocks[current_player.index()].stop() {
+                // The flag fell before the move was made, so it doesn't count.
+                pgn_game.set_tag("Termination", "time forfeit");
+                log(format!("{:?} ran out of time!", current_player));
+                if game_state.has_insufficient_material(current_player.opposite()) {
+                    game_result = GameResult::Draw;
+                    log(format!("Draw, since {:?} can't win!", current_player.opposite()));
+                } else {
+                    game_result = opponent_won;
+                    log(format!("{:?} won!", current_player.opposite()));
+                }
+
+                break;
+            }
+        }
+
+        if player.resigns(&game_state) {
+            game_result = opponent_won;
+            log(format!("{:?} resigned! {:?} won!", current_player, current_player.opposite()));
+            break;
+        }
+
+        let san = pgn_game.record_move(&game_state, &player_move);
+        log(format!("{:?} played {}", current_player, san));
+        game_state.move_piece(&player_move);
+        if let Some(ref clocks) = clocks {
+            log(format!("{:?} has {} left", current_player, clocks[current_player.index()].format()));
+        }
+
+        if player.offers_draw(&game_state) {
+            log(format!("{:?} offers a draw.", current_player));
+            if opponent.accepts_draw(&game_state) {
+                game_result = GameResult::Draw;
+                log("Draw agreed!".to_owned());
+                break;
+            }
+
+            log(format!("{:?} declines the draw.", current_player.opposite()));
+        }
+    }
+
+    log(format!("Game ended on turn {} .", game_state.fullmove_number()));
+    pgn_game.set_result(game_result.pgn_format());
+    log(pgn_game.format());
+    game_result
+}
+
+pub enum GameResult {
+    WhiteWon,
+    BlackWon,
+    Draw,
+}
+
+impl GameResult {
+    pub fn pgn_format(&self) -> &'static str {
+        match *self {
+            GameResult::WhiteWon => "1-0",
+            GameResult::BlackWon => "0-1",
+            GameResult::Draw     => "1/2-1/2",
+        }
+    }
+}
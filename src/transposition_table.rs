@@ -0,0 +1,109 @@
+use std::cmp;
+use std::mem;
+
+use piece_move::Move;
+
+pub const DEFAULT_SIZE_MB: usize = 16;
+// The largest table that can be asked for, which is also what UCI advertises.
+pub const MAX_SIZE_MB: usize = 4096;
+
+// How the stored score relates to the true score of the position.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Bound {
+    Exact,
+    // The search failed high, so the true score is at least this.
+    Lower,
+    // The search failed low, so the true score is at most this.
+    Upper,
+}
+
+#[derive(Clone)]
+pub struct Entry {
+    pub hash: u64,
+    pub depth: u8,
+    pub bound: Bound,
+    pub score: i16,
+    pub best_move: Option<Move>,
+}
+
+// A fixed-size cache of search results keyed by position hash. Each hash maps to a single slot, so
+// colliding positions replace each other, with deeper results being kept over shallower ones for
+// the same position.
+pub struct TranspositionTable {
+    entries: Vec<Option<Entry>>,
+    probes: u64,
+    hits: u64,
+    cutoffs: u64,
+}
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> TranspositionTable {
+        let entry_count = cmp::min(size_mb, MAX_SIZE_MB) * 1024 * 1024 / mem::size_of::<Option<Entry>>();
+        TranspositionTable {
+            // At least one slot is needed for indexing to work.
+            entries: vec![None; entry_count.max(1)],
+            probes: 0,
+            hits: 0,
+            cutoffs: 0,
+        }
+    }
+
+    pub fn probe(&mut self, hash: u64) -> Option<Entry> {
+        self.probes += 1;
+        let index = self.index(hash);
+        match self.entries[index] {
+            Some(ref entry) if entry.hash == hash => {
+                self.hits += 1;
+                Some(entry.clone())
+            },
+            _ => None,
+        }
+    }
+
+    pub fn store(&mut self, entry: Entry) {
+        let index = self.index(entry.hash);
+        let should_replace = match self.entries[index] {
+            Some(ref existing) => existing.hash != entry.hash || existing.depth <= entry.depth,
+            None => true,
+        };
+
+        if should_replace {
+            self.entries[index] = Some(entry);
+        }
+    }
+
+    // Called by the search when a probed entry made searching the position unnecessary.
+    pub fn record_cutoff(&mut self) {
+        self.cutoffs += 1;
+    }
+
+    pub fn clear(&mut self) {
+        for entry in self.entries.iter_mut() {
+            *entry = None;
+        }
+
+        self.reset_stats();
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.probes = 0;
+        self.hits = 0;
+        self.cutoffs = 0;
+    }
+
+    // Occupancy in thousandths, estimated from the first thousand slots as UCI's hashfull expects.
+    pub fn permille_full(&self) -> usize {
+        let sample = &self.entries[..self.entries.len().min(1000)];
+        sample.iter().filter(|entry| entry.is_some()).count() * 1000 / sample.len()
+    }
+
+    pub fn format_stats(&self) -> String {
+        let hit_percentage = if self.probes == 0 { 0.0 } else { 100.0 * self.hits as f32 / self.probes as f32 };
+        format!("Transposition table: {} probes, {:.1}% hits, {} cutoffs, {}/1000 full",
+            self.probes, hit_percentage, self.cutoffs, self.permille_full())
+    }
+
+    fn index(&self, hash: u64) -> usize {
+        (hash % self.entries.len() as u64) as usize
+    }
+}
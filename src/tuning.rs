@@ -0,0 +1,388 @@
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use rand;
+
+use ai_config;
+use ai_config::AiDefinition;
+use ai_config::AI_CONFIG_PATH;
+use cli::Arguments;
+use computer_player;
+use computer_player::ComputerPlayer;
+use computer_player::SearchLimits;
+use computer_player::Weights;
+use computer_player::PieceValues;
+use computer_player::DEFAULT_PIECE_VALUES;
+use computer_player::EVALUATOR_NAMES;
+use computer_player::SCORER_NAMES;
+use game_state::MoveLimitRule;
+use player::Player;
+use random::Random;
+use tournament;
+use tournament::GameSettings;
+
+// The smallest and largest weight for each scorer. Evaluations have to stay well clear of the
+// checkmate scores, and a pawn has to be worth something for the quiescence search's pruning.
+const WEIGHT_RANGES: [(i16, i16); 3] = [(1, 100), (0, 20), (0, 20)];
+
+// How many randomly picked weights compete to be each parent.
+const SELECTION_SIZE: usize = 3;
+
+const DEFAULT_MUTATION_RATE: f64 = 0.2;
+
+// Evolves the weights of multi_eval's scorers. Each generation plays a round robin at a fixed depth,
+// and the next generation is bred from the weights that scored best.
+pub struct TuningSettings {
+    pub population_size: usize,
+    pub generations: u32,
+    pub rounds: u8,
+    pub depth: u8,
+    // How many of the best weights are kept unchanged in the next generation.
+    pub elite_count: usize,
+    // The chance of each weight of a child being changed.
+    pub mutation_rate: f64,
+    // The same seed and settings give the same games and the same result.
+    pub seed: u64,
+    // Written after every generation, and resumed from if it already exists.
+    pub checkpoint_path: Option<String>,
+}
+
+// Everything needed to carry on tuning from the start of a generation.
+struct Checkpoint {
+    generation: u32,
+    rng_state: u64,
+    population: Vec<Weights>,
+    // The best weights of the last generation played, with the points they scored.
+    best: Option<(Weights, f32)>,
+}
+
+pub fn run_tuning(arguments: &Arguments) -> Result<(), String> {
+    let population_size = arguments.parse("population")?.unwrap_or(8);
+    let settings = TuningSettings {
+        population_size: population_size,
+        generations: arguments.parse("generations")?.unwrap_or(10),
+        rounds: arguments.parse("rounds")?.unwrap_or(1),
+        depth: arguments.parse("depth")?.unwrap_or(2),
+        elite_count: (population_size / 4).max(1),
+        mutation_rate: DEFAULT_MUTATION_RATE,
+        seed: arguments.parse("seed")?.unwrap_or_else(rand::random),
+        checkpoint_path: arguments.value("checkpoint").map(|path| path.to_owned()),
+    };
+    if settings.population_size < 2 || settings.depth == 0 {
+        return Err("Tuning needs at least two players and a depth of at least one".to_owned());
+    }
+
+    println!("Tuning with seed {}", settings.seed);
+
+    let (best, points) = tune(&settings).map_err(|error| format!("Tuning failed: {}", error))?;
+    println!("Best weights: {} with {} points", format_weights(&best), points);
+
+    save_as_ai(arguments.value("save-as"), best, DEFAULT_PIECE_VALUES)
+}
+
+// Adds an AI with the tuned weights to the config file, if it was given a name.
+pub fn save_as_ai(name: Option<&str>, weights: Weights, piece_values: PieceValues) -> Result<(), String> {
+    if let Some(name) = name {
+        ai_config::add_definition(AI_CONFIG_PATH, &AiDefinition::new(name, weights, piece_values))?;
+        println!("Saved {} to {}", name, AI_CONFIG_PATH);
+    }
+
+    Ok(())
+}
+
+// Returns the best weights of the last generation, with the points they scored.
+pub fn tune(settings: &TuningSettings) -> Result<(Weights, f32), String> {
+    let checkpoint = match settings.checkpoint_path {
+        Some(ref path) => load_checkpoint(path)?,
+        None => None,
+    };
+    let mut checkpoint = match checkpoint {
+        Some(checkpoint) => {
+            println!("Resuming from generation {}", checkpoint.generation + 1);
+            checkpoint
+        },
+        None => {
+            let mut rng = Random::new(settings.seed);
+            let population = initial_population(settings.population_size, &mut rng);
+            Checkpoint { generation: 0, rng_state: rng.state(), population: population, best: None }
+        },
+    };
+
+    while checkpoint.generation < settings.generations {
+        let mut rng = Random::new(checkpoint.rng_state);
+        let points = play_generation(&checkpoint.population, settings, &mut rng);
+        let ranking = rank(&points);
+        let best = (checkpoint.population[ranking[0]], points[ranking[0]]);
+        println!("Generation {}: best weights {} scored {} of {} points",
+            checkpoint.generation + 1, format_weights(&best.0), best.1,
+            (checkpoint.population.len() - 1) * 2 * settings.rounds as usize);
+
+        let population = breed(&checkpoint.population, &ranking, settings, &mut rng);
+        checkpoint = Checkpoint {
+            generation: checkpoint.generation + 1,
+            rng_state: rng.state(),
+            population: population,
+            best: Some(best),
+        };
+
+        if let Some(ref path) = settings.checkpoint_path {
+            save_checkpoint(path, &checkpoint)
+                .map_err(|error| format!("Failed to write {}: {}", path, error))?;
+        }
+    }
+
+    checkpoint.best.ok_or("No generations were played".to_owned())
+}
+
+// Starts from the built in AIs' weights, with random weights for the rest.
+fn initial_population(size: usize, rng: &mut Random) -> Vec<Weights> {
+    let mut population = EVALUATOR_NAMES.iter()
+        .take(size)
+        .map(|name| computer_player::evaluator_weights(name).unwrap())
+        .collect::<Vec<_>>();
+    while population.len() < size {
+        let mut weights = [0; 3];
+        for (weight, &(low, high)) in weights.iter_mut().zip(WEIGHT_RANGES.iter()) {
+            *weight = rng.between(low, high);
+        }
+
+        population.push(weights);
+    }
+
+    population
+}
+
+// Plays the population's round robin, returning the points each scored: one for a win and a half for
+// a draw.
+fn play_generation(population: &[Weights], settings: &TuningSettings, rng: &mut Random) -> Vec<f32> {
+    let mut players = population.iter().enumerate().map(|(i, weights)| {
+        let mut player = ComputerPlayer::new(&format!("{} ({})", i + 1, format_weights(weights)),
+            computer_player::weighted_evaluator(*weights, DEFAULT_PIECE_VALUES));
+        player.set_verbose(false);
+        player.set_hash_size(1);
+        player.set_seed(rng.next_u64());
+        Box::new(player) as Box<dyn Player>
+    }).collect::<Vec<_>>();
+    let game_settings = GameSettings {
+        time_control: None,
+        limits: SearchLimits::depth(settings.depth),
+        move_limit_rule: MoveLimitRule::FiftyMove,
+        verbose: false,
+    };
+    let results = tournament::round_robin(&mut players, settings.rounds, &game_settings, "Tuning", None);
+
+    (0..population.len()).map(|i| (0..population.len()).fold(0f32, |points, j| {
+        let (as_white, as_black) = (results[i][j], results[j][i]);
+        points + (as_white.white_wins + as_black.black_wins) as f32 + (as_white.draws + as_black.draws) as f32 / 2.0
+    })).collect()
+}
+
+// Indexes of the population from most to fewest points.
+fn rank(points: &[f32]) -> Vec<usize> {
+    let mut ranking = (0..points.len()).collect::<Vec<_>>();
+    ranking.sort_by(|&a, &b| points[b].partial_cmp(&points[a]).unwrap());
+    ranking
+}
+
+fn breed(
+        population: &[Weights],
+        ranking: &[usize],
+        settings: &TuningSettings,
+        rng: &mut Random) -> Vec<Weights> {
+
+
+    let mut children = ranking.iter()
+        .take(settings.elite_count)
+        .map(|&i| population[i])
+        .collect::<Vec<_>>();
+    while children.len() < population.len() {
+        let mother = population[select_parent(ranking, rng)];
+        let father = population[select_parent(ranking, rng)];
+        let mut child = [0; 3];
+        for i in 0..child.len() {
+            child[i] = if rng.chance(0.5) { mother[i] } else { father[i] };
+            if rng.chance(settings.mutation_rate) {
+                let (low, high) = WEIGHT_RANGES[i];
+                let step = ((high - low) / 10).max(1);
+                child[i] = (child[i] + rng.between(-step, step)).max(low).min(high);
+            }
+        }
+
+        children.push(child);
+    }
+
+    children
+}
+
+// Picks the best ranked of a few random members of the population.
+fn select_parent(ranking: &[usize], rng: &mut Random) -> usize {
+    let best_rank = (0..SELECTION_SIZE).map(|_| rng.below(ranking.len())).min().unwrap();
+    ranking[best_rank]
+}
+
+pub fn format_weights(weights: &Weights) -> String {
+    weights.iter().map(|weight| weight.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+// Weights are written as one number per scorer, separated by spaces.
+pub fn parse_weights(text: &str) -> Option<Weights> {
+    let numbers = text.split_whitespace().map(|number| number.parse().ok()).collect::<Option<Vec<i16>>>()?;
+    if numbers.len() != SCORER_NAMES.len() {
+        return None;
+    }
+
+    let mut weights = [0; 3];
+    weights.copy_from_slice(&numbers);
+    Some(weights)
+}
+
+// Checkpoints are written as lines such as "generation 3", "rng 12345", "best 40 5 2 9.5", and one
+// "weights 40 5 2" for each member of the population.
+fn save_checkpoint(path: &str, checkpoint: &Checkpoint) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    writeln!(file, "generation {}", checkpoint.generation)?;
+    writeln!(file, "rng {}", checkpoint.rng_state)?;
+    if let Some((ref weights, points)) = checkpoint.best {
+        writeln!(file, "best {} {}", format_weights(weights), points)?;
+    }
+
+    for weights in checkpoint.population.iter() {
+        writeln!(file, "weights {}", format_weights(weights))?;
+    }
+
+    Ok(())
+}
+
+// No checkpoint is returned if the file doesn't exist yet.
+fn load_checkpoint(path: &str) -> Result<Option<Checkpoint>, String> {
+    let mut text = String::new();
+    match File::open(path) {
+        Ok(mut file) => file.read_to_string(&mut text)
+            .map_err(|error| format!("Failed to read {}: {}", path, error))?,
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(format!("Failed to open {}: {}", path, error)),
+    };
+
+    let invalid = |line: &str| format!("Invalid line in checkpoint {}: {}", path, line);
+    let mut generation = None;
+    let mut rng_state = None;
+    let mut best = None;
+    let mut population = vec![];
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        let mut parts = line.trim().splitn(2, ' ');
+        let key = parts.next().unwrap();
+        let value = parts.next().unwrap_or("");
+        match key {
+            "generation" => generation = Some(value.parse().map_err(|_| invalid(line))?),
+            "rng" => rng_state = Some(value.parse().map_err(|_| invalid(line))?),
+            "best" => {
+                let split = value.rfind(' ').ok_or(invalid(line))?;
+                let weights = parse_weights(&value[..split]).ok_or(invalid(line))?;
+                let points = value[split + 1..].parse().map_err(|_| invalid(line))?;
+                best = Some((weights, points));
+            },
+            "weights" => population.push(parse_weights(value).ok_or(invalid(line))?),
+            _ => return Err(invalid(line)),
+        }
+    }
+
+    match (generation, rng_state) {
+        (Some(generation), Some(rng_state)) if !population.is_empty() => Ok(Some(Checkpoint {
+            generation: generation,
+            rng_state: rng_state,
+            population: population,
+            best: best,
+        })),
+        _ => Err(format!("Checkpoint {} is incomplete", path)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    fn settings(generations: u32, checkpoint_path: Option<String>) -> TuningSettings {
+        TuningSettings {
+            population_size: 3,
+            generations: generations,
+            rounds: 1,
+            depth: 1,
+            elite_count: 1,
+            mutation_rate: 0.5,
+            seed: 7,
+            checkpoint_path: checkpoint_path,
+        }
+    }
+
+    // A path in the temporary directory that no other test run is using.
+    fn temp_path(name: &str) -> String {
+        let path = env::temp_dir().join(format!("chess_tuning_{}_{}", name, process::id()));
+        let _ = fs::remove_file(&path);
+        path.to_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn same_seed_same_population() {
+        let population = |seed| initial_population(8, &mut Random::new(seed));
+        assert_eq!(population(1), population(1));
+        assert!(population(1) != population(2));
+        // The built in AIs come first, and the rest stay in range.
+        assert_eq!(population(1)[0], computer_player::evaluator_weights(EVALUATOR_NAMES[0]).unwrap());
+        for weights in population(1) {
+            for (&weight, &(low, high)) in weights.iter().zip(WEIGHT_RANGES.iter()) {
+                assert!(weight >= low && weight <= high, "{:?}", weights);
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_same_winner() {
+        assert_eq!(tune(&settings(1, None)), tune(&settings(1, None)));
+    }
+
+    #[test]
+    fn checkpoint_round_trip() {
+        let path = temp_path("round_trip");
+        let checkpoint = Checkpoint {
+            generation: 3,
+            rng_state: 12345678901234567890,
+            population: vec![[40, 5, 2], [1, 0, 20], [100, 20, 0]],
+            best: Some(([40, 5, 2], 9.5)),
+        };
+        save_checkpoint(&path, &checkpoint).unwrap();
+        let loaded = load_checkpoint(&path).unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.generation, checkpoint.generation);
+        assert_eq!(loaded.rng_state, checkpoint.rng_state);
+        assert_eq!(loaded.population, checkpoint.population);
+        assert_eq!(loaded.best, checkpoint.best);
+        assert!(load_checkpoint(&path).unwrap().is_none());
+    }
+
+    // Stopping after a generation and resuming from its checkpoint has to give the same result as
+    // playing every generation in one go.
+    #[test]
+    fn resume_from_checkpoint() {
+        let uninterrupted_path = temp_path("uninterrupted");
+        let uninterrupted = tune(&settings(2, Some(uninterrupted_path.clone())));
+
+        let path = temp_path("resumed");
+        tune(&settings(1, Some(path.clone()))).unwrap();
+        assert_eq!(load_checkpoint(&path).unwrap().unwrap().generation, 1);
+        let resumed = tune(&settings(2, Some(path.clone())));
+        let checkpoint = load_checkpoint(&path).unwrap().unwrap();
+        let uninterrupted_checkpoint = load_checkpoint(&uninterrupted_path).unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&uninterrupted_path).unwrap();
+
+        assert_eq!(resumed, uninterrupted);
+        assert_eq!(checkpoint.generation, 2);
+        assert_eq!(checkpoint.rng_state, uninterrupted_checkpoint.rng_state);
+        assert_eq!(checkpoint.population, uninterrupted_checkpoint.population);
+    }
+}
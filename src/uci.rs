@@ -0,0 +1,224 @@
+use std::cmp;
+use std::io;
+use std::io::BufRead;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use std::u32;
+
+use ai_config;
+use ai_config::AiDefinition;
+use computer_player;
+use computer_player::MAX_DEPTH;
+use computer_player::SearchLimits;
+use game_state::Color;
+use game_state::GameState;
+use piece_move::Move;
+use transposition_table;
+use transposition_table::TranspositionTable;
+
+const DEFAULT_PLAYER: &'static str = "spaces_moves";
+
+// Speaks the Universal Chess Interface over stdin/stdout so that the AIs can be run from chess GUIs
+// and tournament managers. The GUI starts by sending "uci", which is answered like any other command.
+pub fn run_uci() {
+    let definitions = ai_config::load_definitions(ai_config::AI_CONFIG_PATH).unwrap_or_else(|error| {
+        println!("info string {}", error);
+        ai_config::builtin_definitions()
+    });
+    let mut player = definitions.iter()
+        .find(|definition| definition.name == DEFAULT_PLAYER)
+        .unwrap_or(&definitions[0])
+        .clone();
+    let default_player = player.name.clone();
+
+    let mut game_state = GameState::opening_state();
+    // Kept between searches so that each one benefits from the last. It is lent to the search
+    // thread while searching.
+    let mut table = Some(TranspositionTable::new(transposition_table::DEFAULT_SIZE_MB));
+    let mut search = None;
+
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let line = line.unwrap();
+        let tokens = line.split_whitespace().collect::<Vec<_>>();
+        match tokens.first() {
+            Some(&"uci") => identify(&definitions, &default_player),
+            Some(&"isready") => println!("readyok"),
+            Some(&"ucinewgame") => {
+                stop_search(&mut search, &mut table);
+                table.as_mut().unwrap().clear();
+                game_state = GameState::opening_state();
+            },
+            Some(&"setoption") => {
+                stop_search(&mut search, &mut table);
+                match parse_option(&tokens[1..]) {
+                    Some((ref name, ref value)) if name == "Player"
+                            && definitions.iter().any(|definition| &definition.name == value) =>
+                        player = definitions.iter().find(|definition| &definition.name == value).unwrap().clone(),
+                    Some((ref name, ref value)) if name == "Hash" && parse_hash_size(value).is_some() =>
+                        table = Some(TranspositionTable::new(parse_hash_size(value).unwrap())),
+                    _ => println!("info string Unsupported option: {}", line),
+                }
+            },
+            Some(&"position") => {
+                stop_search(&mut search, &mut table);
+                match parse_position(&tokens[1..]) {
+                    Some(new_game_state) => game_state = new_game_state,
+                    None => println!("info string Invalid position: {}", line),
+                }
+            },
+            Some(&"go") => {
+                stop_search(&mut search, &mut table);
+                let limits = parse_go(&tokens[1..], game_state.current_player);
+                let stop = Arc::new(AtomicBool::new(false));
+                let handle = start_search(
+                    game_state.clone(), player.clone(), limits, stop.clone(), table.take().unwrap());
+                search = Some((stop, handle));
+            },
+            Some(&"stop") => stop_search(&mut search, &mut table),
+            Some(&"quit") => {
+                stop_search(&mut search, &mut table);
+                return;
+            },
+            // The protocol requires unknown commands to be ignored.
+            _ => (),
+        }
+    }
+
+    stop_search(&mut search, &mut table);
+}
+
+fn identify(definitions: &[AiDefinition], default_player: &str) {
+    println!("id name chess");
+    println!("id author sean");
+    println!("option name Player type combo default {}{}",
+        default_player,
+        definitions.iter().fold("".to_owned(), |text, definition| format!("{} var {}", text, definition.name)));
+    println!("option name Hash type spin default {} min 1 max {}",
+        transposition_table::DEFAULT_SIZE_MB, transposition_table::MAX_SIZE_MB);
+    println!("uciok");
+}
+
+// Parses "name <name> value <value>" from a setoption command.
+fn parse_option(tokens: &[&str]) -> Option<(String, String)> {
+    let value_index = tokens.iter().position(|&token| token == "value");
+    match (tokens.first(), value_index) {
+        (Some(&"name"), Some(value_index)) =>
+            Some((tokens[1..value_index].join(" "), tokens[value_index + 1..].join(" "))),
+        _ => None,
+    }
+}
+
+// Parses "startpos [moves ...]" or "fen <fen> [moves ...]".
+fn parse_position(tokens: &[&str]) -> Option<GameState> {
+    let moves_index = tokens.iter().position(|&token| token == "moves").unwrap_or(tokens.len());
+    let mut game_state = match tokens.first() {
+        Some(&"startpos") => GameState::opening_state(),
+        Some(&"fen") => match GameState::from_fen(&tokens[1..moves_index].join(" ")) {
+            Some(game_state) => game_state,
+            None => return None,
+        },
+        _ => return None,
+    };
+
+    for move_text in tokens.iter().skip(moves_index + 1) {
+        match find_legal_move(&game_state, move_text) {
+            Some(player_move) => game_state.move_piece(&player_move),
+            None => return None,
+        }
+    }
+
+    Some(game_state)
+}
+
+// UCI moves are in the same long algebraic form as Move::simple_format.
+fn find_legal_move(game_state: &GameState, move_text: &str) -> Option<Move> {
+    game_state.get_legal_moves().into_iter()
+        .find(|player_move| player_move.simple_format() == move_text)
+}
+
+// Waits for any running search to finish, taking back the transposition table that it borrowed.
+fn stop_search(
+        search: &mut Option<(Arc<AtomicBool>, JoinHandle<TranspositionTable>)>,
+        table: &mut Option<TranspositionTable>) {
+
+    if let Some((stop, handle)) = search.take() {
+        stop.store(true, Ordering::SeqCst);
+        *table = Some(handle.join().unwrap());
+    }
+}
+
+// Searches one depth at a time so that there is always a result to report when time runs out or
+// "stop" arrives. Only the first depth is always completed. Any later one is abandoned part way
+// through, and the best move of the last completed depth is played.
+fn start_search(
+        game_state: GameState,
+        player: AiDefinition,
+        limits: SearchLimits,
+        stop: Arc<AtomicBool>,
+        mut table: TranspositionTable) -> JoinHandle<TranspositionTable> {
+
+    thread::spawn(move || {
+        let moves = game_state.get_legal_moves();
+        if moves.is_empty() {
+            println!("bestmove 0000");
+            return table;
+        }
+
+        table.reset_stats();
+        // Only the evaluation is taken from the AI, since the GUI sets the limits and the hash size.
+        let evaluator = player.evaluator();
+        let iteration = computer_player::iterative_deepening(
+            &game_state, &moves, &evaluator, &limits, &mut table, stop, &mut |iteration| {
+                let score = iteration.move_scores[0].1;
+                let score = match computer_player::moves_to_mate(score) {
+                    Some(moves_to_mate) => format!("mate {}", moves_to_mate),
+                    None => format!("cp {}", evaluator.centipawns(score)),
+                };
+                let principal_variation = iteration.principal_variation.iter()
+                    .map(|player_move| player_move.simple_format())
+                    .collect::<Vec<_>>();
+                println!("info depth {} score {} time {} pv {}",
+                    iteration.depth, score, duration_millis(&iteration.elapsed), principal_variation.join(" "));
+            });
+
+        println!("info hashfull {} string {}", table.permille_full(), table.format_stats());
+        println!("bestmove {}", iteration.move_scores[0].0.simple_format());
+        table
+    })
+}
+
+fn duration_millis(duration: &Duration) -> u64 {
+    duration.as_secs() * 1000 + duration.subsec_nanos() as u64 / 1000000
+}
+
+// Table sizes in megabytes are limited to the range that "uci" advertises.
+fn parse_hash_size(value: &str) -> Option<usize> {
+    value.parse().ok().filter(|&size_mb| size_mb >= 1 && size_mb <= transposition_table::MAX_SIZE_MB)
+}
+
+// Reads the limits of a "go" command, keeping only the clock of the player to move.
+fn parse_go(tokens: &[&str], color: Color) -> SearchLimits {
+    let value_of = |name: &str| tokens.iter()
+        .position(|&token| token == name)
+        .and_then(|index| tokens.get(index + 1))
+        .and_then(|value| value.parse::<u64>().ok());
+
+    let (time_name, increment_name) = match color {
+        Color::White => ("wtime", "winc"),
+        Color::Black => ("btime", "binc"),
+    };
+
+    SearchLimits {
+        depth: value_of("depth").map(|depth| cmp::max(1, cmp::min(depth, MAX_DEPTH as u64)) as u8),
+        movetime: value_of("movetime").map(Duration::from_millis),
+        time_left: value_of(time_name).map(Duration::from_millis),
+        increment: Duration::from_millis(value_of(increment_name).unwrap_or(0)),
+        moves_to_go: value_of("movestogo").map(|moves_to_go| cmp::min(moves_to_go, u32::MAX as u64) as u32),
+        infinite: tokens.contains(&"infinite"),
+    }
+}
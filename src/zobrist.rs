@@ -0,0 +1,35 @@
+use game_state::Color;
+use piece_type::PieceType;
+use position::Position;
+
+// Zobrist keys are derived by hashing an index for each feature of a position rather than being
+// looked up in a random table, so that they are the same on every run without any setup.
+const BLACK_TO_MOVE_INDEX: u64 = 2 * 6 * 64;
+const CASTLING_INDEX: u64 = BLACK_TO_MOVE_INDEX + 1;
+const EN_PASSANT_INDEX: u64 = CASTLING_INDEX + 4;
+
+pub fn piece_key(piece_type: PieceType, color: Color, position: &Position) -> u64 {
+    let square_index = (position.row * 8 + position.column) as u64;
+    key(((color.index() * 6 + piece_type.index()) as u64) * 64 + square_index)
+}
+
+pub fn black_to_move_key() -> u64 {
+    key(BLACK_TO_MOVE_INDEX)
+}
+
+// Castling rights are numbered White kingside, White queenside, Black kingside, Black queenside.
+pub fn castling_key(castling_right_index: usize) -> u64 {
+    key(CASTLING_INDEX + castling_right_index as u64)
+}
+
+pub fn en_passant_key(column: i8) -> u64 {
+    key(EN_PASSANT_INDEX + column as u64)
+}
+
+// The SplitMix64 finalizer, which spreads consecutive indexes across all 64 bits.
+fn key(index: u64) -> u64 {
+    let mut z = index.wrapping_add(1).wrapping_mul(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}